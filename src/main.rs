@@ -1,13 +1,32 @@
 extern crate core;
 
-use std::sync::mpsc;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 use clap::Parser;
 pub mod sh;
 
+use crate::sh::bitmask_device::BitmaskSenders;
+#[cfg(target_os = "linux")]
+use crate::sh::bitmask_device::{update_bitmask_devices, BitmaskDevice, BitmaskMessage};
+use crate::sh::clients::Clients;
+use crate::sh::config_file::ConfigFile;
+use crate::sh::config_watcher::start_config_watcher;
+use crate::sh::gamepad_data::GamepadMessage;
+use crate::sh::gesture_recognizer::StrokeMessage;
+use crate::sh::keyboard_data::KeyboardMessage;
+use crate::sh::mouse_data::MouseMessage;
+use crate::sh::osu_mode::OsuMessage;
+use crate::sh::sensor_data::SensorMessage;
+use crate::sh::gesture_input;
+use crate::sh::macro_input;
+use crate::sh::sensor_input;
 use crate::sh::server::Server;
-use crate::sh::senders::Senders;
+use crate::sh::session::{self, SessionRecorder};
+use crate::sh::senders::{Receivers, Senders};
+use crate::sh::pointer_smoothing::PointerSmoothing;
 
 #[cfg(target_os = "linux")]
 pub mod sh_linux;
@@ -17,6 +36,20 @@ use crate::sh_linux::linux::mouse_input;
 use crate::sh_linux::linux::keyboard_input;
 #[cfg(target_os = "linux")]
 use crate::sh_linux::linux::osu_input;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::touchpad_input;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::pen_input;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::gamepad_input;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::dial_input;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::bitmask_input;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::sighup;
+#[cfg(target_os = "linux")]
+use crate::sh_linux::linux::key_remap_config::load_bitmask_devices;
 
 #[cfg(target_os = "windows")]
 pub mod sh_windows;
@@ -28,32 +61,149 @@ use crate::sh_windows::windows::mouse_input;
 struct Config{
     #[clap(short = 'p', long = "port", default_value = "2137")]
     port: String,
+    /// Configuration file, settings changed by clients are written back to it.
+    #[clap(short = 'c', long = "config", default_value = "sensorhandler.conf")]
+    config: PathBuf,
+    /// Spread mouse motion over sub-steps emitted at this rate (Hz). Disabled when not set.
+    #[clap(long = "smoothing-rate")]
+    smoothing_rate: Option<u32>,
+    /// Latency budget (ms) the smoothed motion may lag behind the client.
+    #[clap(long = "smoothing-latency", default_value = "16")]
+    smoothing_latency: u64,
+    /// Write every message received from clients to this session file.
+    #[clap(long = "record")]
+    record: Option<PathBuf>,
+    /// Play a recorded session file instead of listening for clients.
+    #[clap(long = "replay")]
+    replay: Option<PathBuf>,
+    /// Speed multiplier of the replay.
+    #[clap(long = "replay-speed", default_value = "1.0")]
+    replay_speed: f64,
 }
 
 fn main() {
     let config = Config::parse();
-    let (senders, osu_receiver, keyboard_receiver, mouse_receiver) = create_channels();
+    let config_file = match ConfigFile::load(&config.config) {
+        Ok(config_file) => config_file,
+        Err(error) => {
+            println!("Failed to read the configuration file, using defaults.");
+            println!("{}", error);
+            ConfigFile::default()
+        }
+    };
+    let config_file = Arc::new(Mutex::new(config_file));
+    let (senders, receivers) = create_channels();
+    let clients = Clients::default();
+
+    #[cfg(target_os = "linux")]
+    let smoothing = config.smoothing_rate.map(|rate| PointerSmoothing::new(rate, Duration::from_millis(config.smoothing_latency)));
 
     #[cfg(target_os = "linux")]
-    thread::spawn(move || mouse_input::start_mouse_input(mouse_receiver));
+    let mouse_config = config_file.clone();
+    #[cfg(target_os = "linux")]
+    thread::spawn(move || mouse_input::start_mouse_input(receivers.mouse_receiver, smoothing, mouse_config));
+    #[cfg(target_os = "linux")]
+    let (keyboard_config, keyboard_clients) = (config_file.clone(), clients.clone());
+    #[cfg(target_os = "linux")]
+    thread::spawn(move || keyboard_input::start_keyboard_input(receivers.keyboard_receiver, keyboard_config, keyboard_clients));
+    #[cfg(target_os = "linux")]
+    let (osu_config, osu_clients) = (config_file.clone(), clients.clone());
     #[cfg(target_os = "linux")]
-    thread::spawn(move || keyboard_input::start_keyboard_input(keyboard_receiver));
+    thread::spawn(move || osu_input::start_osu_input(receivers.osu_receiver, osu_config, osu_clients));
     #[cfg(target_os = "linux")]
-    thread::spawn(move || osu_input::start_osu_input(osu_receiver));
+    thread::spawn(move || touchpad_input::start_touchpad_input(receivers.touchpad_receiver));
+    #[cfg(target_os = "linux")]
+    thread::spawn(move || pen_input::start_pen_input(receivers.pen_receiver));
+    #[cfg(target_os = "linux")]
+    let (gamepad_config, gamepad_clients) = (config_file.clone(), clients.clone());
+    #[cfg(target_os = "linux")]
+    thread::spawn(move || gamepad_input::start_gamepad_input(receivers.gamepad_receiver, gamepad_config, gamepad_clients));
+    #[cfg(target_os = "linux")]
+    thread::spawn(move || dial_input::start_dial_input(receivers.dial_receiver));
+
+    // Devices defined in the config come and go with it.
+    #[cfg(target_os = "linux")]
+    {
+        let bitmask_config = config_file.clone();
+        let start_bitmask_device = move |device: BitmaskDevice<_>, receiver: mpsc::Receiver<BitmaskMessage>| {
+            let bitmask_config = bitmask_config.clone();
+            thread::spawn(move || bitmask_input::start_bitmask_input(receiver, device, bitmask_config));
+        };
+
+        let mut bitmask_devices = vec![];
+        update_bitmask_devices(&mut bitmask_devices, load_bitmask_devices(&config_file.lock().unwrap()), &senders.bitmask_senders, &start_bitmask_device);
 
+        let (watcher_config, bitmask_senders) = (config_file.clone(), senders.bitmask_senders.clone());
+        let reload_requested = sighup::watch_sighup();
+        thread::spawn(move || start_config_watcher(watcher_config, reload_requested, move |config| {
+            update_bitmask_devices(&mut bitmask_devices, load_bitmask_devices(config), &bitmask_senders, &start_bitmask_device);
+        }));
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        static RELOAD_REQUESTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        let watcher_config = config_file.clone();
+        thread::spawn(move || start_config_watcher(watcher_config, &RELOAD_REQUESTED, |_| {}));
+    }
+
+    #[cfg(target_os = "windows")]
+    let mouse_config = config_file.clone();
     #[cfg(target_os = "windows")]
-    thread::spawn(move || mouse_input::start_mouse_input(mouse_receiver));
+    thread::spawn(move || mouse_input::start_mouse_input(receivers.mouse_receiver, mouse_config));
+
+    let (sensor_mouse_sender, sensor_gamepad_sender) = (senders.mouse_sender.clone(), senders.gamepad_sender.clone());
+    let (sensor_config, sensor_clients) = (config_file.clone(), clients.clone());
+    thread::spawn(move || sensor_input::start_sensor_input(receivers.sensor_receiver, sensor_mouse_sender, sensor_gamepad_sender, sensor_config, sensor_clients));
+
+    let (stroke_keyboard_sender, stroke_mouse_sender) = (senders.keyboard_sender.clone(), senders.mouse_sender.clone());
+    let stroke_config = config_file.clone();
+    thread::spawn(move || gesture_input::start_gesture_input(receivers.stroke_receiver, stroke_keyboard_sender, stroke_mouse_sender, stroke_config));
+
+    let (macro_keyboard_sender, macro_mouse_sender) = (senders.keyboard_sender.clone(), senders.mouse_sender.clone());
+    let macro_config = config_file.clone();
+    thread::spawn(move || macro_input::start_macro_input(receivers.macro_receiver, macro_keyboard_sender, macro_mouse_sender, macro_config));
+
+    if let Some(path) = &config.replay {
+        println!("Replaying session: {}", path.display());
+        if let Err(error) = session::replay(path, config.replay_speed, &senders) {
+            println!("Failed to replay the session.");
+            println!("{}", error);
+        }
+        // Give the device threads a moment to handle the last messages.
+        thread::sleep(Duration::from_millis(500));
+        return;
+    }
 
     let mut server = Server::new(&config.port);
     server.set_senders(senders);
+    server.set_clients(clients);
+    if let Some(path) = &config.record {
+        match SessionRecorder::create(path) {
+            Ok(recorder) => server.set_recorder(recorder),
+            Err(error) => {
+                println!("Failed to create the session file, not recording.");
+                println!("{}", error);
+            }
+        }
+    }
     server.start();
 }
 
-pub fn create_channels() -> (Senders, mpsc::Receiver<String>, mpsc::Receiver<String>, mpsc::Receiver<String>){
-    let (keyboard_sender, keyboard_receiver) = mpsc::channel::<String>();
-    let (osu_sender, osu_receiver) = mpsc::channel::<String>();
-    let (mouse_sender,mouse_receiver) = mpsc::channel::<String>();
+pub fn create_channels() -> (Senders, Receivers){
+    let (keyboard_sender, keyboard_receiver) = mpsc::channel::<KeyboardMessage>();
+    let (osu_sender, osu_receiver) = mpsc::channel::<OsuMessage>();
+    let (mouse_sender,mouse_receiver) = mpsc::channel::<MouseMessage>();
+    let (touchpad_sender, touchpad_receiver) = mpsc::channel::<String>();
+    let (pen_sender, pen_receiver) = mpsc::channel::<String>();
+    let (gamepad_sender, gamepad_receiver) = mpsc::channel::<GamepadMessage>();
+    let (sensor_sender, sensor_receiver) = mpsc::channel::<SensorMessage>();
+    let (stroke_sender, stroke_receiver) = mpsc::channel::<StrokeMessage>();
+    let (dial_sender, dial_receiver) = mpsc::channel::<String>();
+    let (macro_sender, macro_receiver) = mpsc::channel::<String>();
+    let bitmask_senders = BitmaskSenders::default();
 
-    (Senders{osu_sender, keyboard_sender, mouse_sender }, osu_receiver, keyboard_receiver, mouse_receiver)
+    (
+        Senders{osu_sender, keyboard_sender, mouse_sender, touchpad_sender, pen_sender, gamepad_sender, sensor_sender, stroke_sender, dial_sender, macro_sender, bitmask_senders },
+        Receivers{osu_receiver, keyboard_receiver, mouse_receiver, touchpad_receiver, pen_receiver, gamepad_receiver, sensor_receiver, stroke_receiver, dial_receiver, macro_receiver },
+    )
 }
-
@@ -2,3 +2,34 @@ pub mod senders;
 pub mod server;
 pub mod key_input;
 pub mod mouse_data;
+pub mod pointer_smoothing;
+pub mod config_file;
+pub mod mouse_settings;
+pub mod touch_data;
+pub mod pen_data;
+pub mod gamepad_data;
+pub mod sensor_data;
+pub mod air_mouse;
+pub mod sensor_input;
+pub mod tilt_mapper;
+pub mod clients;
+pub mod rumble;
+pub mod led_state;
+pub mod player_slots;
+pub mod keyboard_data;
+pub mod stick_mapper;
+pub mod sensor_fusion;
+pub mod key_names;
+pub mod key_remap;
+pub mod gesture_recognizer;
+pub mod gesture_input;
+pub mod dial_data;
+pub mod osu_mode;
+pub mod tap_stats;
+pub mod session;
+pub mod macros;
+pub mod macro_input;
+pub mod key_behaviors;
+pub mod typematic;
+pub mod bitmask_device;
+pub mod config_watcher;
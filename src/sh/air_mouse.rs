@@ -0,0 +1,96 @@
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_turn_rotation_into_pointer_motion(){
+    let mut air_mouse = AirMouse::new(AirMouseSettings{ sensitivity: 1000.0, deadzone: 0.0 });
+
+    assert!(air_mouse.update([0.0, 0.0, 0.0], false, 0.0).is_none());
+    // Turning right around the screen normal and tilting the top up for 10 ms.
+    assert_eq!(air_mouse.update([0.5, 0.0, -1.0], false, 0.01), Some((10.0, -5.0)));
+    assert!(air_mouse.update([0.5, 0.0, -1.0], true, 0.02).is_none());
+}
+
+#[test]
+fn should_ignore_rotation_inside_deadzone(){
+    let mut air_mouse = AirMouse::new(AirMouseSettings{ sensitivity: 1000.0, deadzone: 0.1 });
+
+    air_mouse.update([0.0, 0.0, 0.0], false, 0.0);
+    assert!(air_mouse.update([0.05, 0.0, -0.09], false, 0.01).is_none());
+}
+
+const SECTION: &str = "air_mouse";
+// Longer gaps between samples are treated as a pause, not as a long rotation.
+const MAX_STEP: f64 = 0.05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirMouseSettings {
+    /// Pointer travel in pixels per radian of rotation.
+    pub sensitivity: f32,
+    /// Angular velocity in rad/s that is ignored to hide sensor noise and hand tremor.
+    pub deadzone: f32,
+}
+
+impl AirMouseSettings {
+    pub fn from_config(config: &ConfigFile) -> Self {
+        Self{
+            sensitivity: config.get_or(SECTION, "sensitivity", 800.0),
+            deadzone: config.get_or(SECTION, "deadzone", 0.03),
+        }
+    }
+}
+
+/// Turns the phone's angular velocity into pointer motion, like a presenter air mouse.
+///
+/// Yaw (rotation around the screen normal) moves the pointer sideways and pitch (rotation
+/// around the screen's x axis) moves it up and down. While the clutch is held the pointer
+/// stays put, so the phone can be re-centred.
+#[derive(Debug)]
+pub struct AirMouse {
+    settings: AirMouseSettings,
+    last_time: Option<f64>,
+    remainder: (f32, f32),
+}
+
+impl AirMouse {
+    pub fn new(settings: AirMouseSettings) -> Self {
+        Self{ settings, last_time: None, remainder: (0.0, 0.0) }
+    }
+
+    /// Feeds one gyroscope sample taken at `time` seconds, returns whole pixels to move.
+    pub fn update(&mut self, gyro: [f32; 3], clutch: bool, time: f64) -> Option<(f32, f32)>{
+        let delta_time = match self.last_time {
+            Some(last_time) => (time - last_time).clamp(0.0, MAX_STEP) as f32,
+            None => 0.0,
+        };
+        self.last_time = Some(time);
+
+        if clutch {
+            self.remainder = (0.0, 0.0);
+            return None;
+        }
+
+        let yaw = self.deadzone(gyro[2]);
+        let pitch = self.deadzone(gyro[0]);
+
+        self.remainder.0 += -yaw * delta_time * self.settings.sensitivity;
+        self.remainder.1 += -pitch * delta_time * self.settings.sensitivity;
+
+        let x = self.remainder.0.round();
+        let y = self.remainder.1.round();
+        self.remainder.0 -= x;
+        self.remainder.1 -= y;
+
+        if x == 0.0 && y == 0.0 {
+            return None;
+        }
+
+        Some((x, y))
+    }
+
+    fn deadzone(&self, value: f32) -> f32 {
+        if value.abs() <= self.settings.deadzone {
+            return 0.0;
+        }
+        value - self.settings.deadzone * value.signum()
+    }
+}
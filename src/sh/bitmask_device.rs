@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+use super::clients::ClientId;
+use super::config_file::ConfigFile;
+use super::server::Server;
+
+#[test]
+fn should_load_devices_from_config(){
+    let config = ConfigFile::parse("[device PEDALS]\nname = Foot pedals\nkeys = a, b, btn_left\nwidth = 8\n[device KEYBOARD]\nkeys = a\n[device EMPTY]\nname = Nothing\n[device WIDE]\nkeys = a, b, c\nwidth = 2\n[device TYPO]\nkeys = a, nope\n");
+    let devices = load_bitmask_devices(&config, |name| match name {
+        "nope" => None,
+        name => Some(name.to_string()),
+    });
+
+    assert_eq!(devices, vec![BitmaskDevice{
+        protocol: "PEDALS".to_string(),
+        name: "Foot pedals".to_string(),
+        keys: vec!["a".to_string(), "b".to_string(), "btn_left".to_string()],
+        width: 8,
+    }]);
+}
+
+#[test]
+fn should_limit_masks_to_width(){
+    let device = BitmaskDevice::<char>{ protocol: "PEDALS".to_string(), name: String::new(), keys: vec![], width: 4 };
+
+    assert_eq!(device.parse_mask("21"), Some(0b0101));
+    assert_eq!(device.parse_mask("nope"), None);
+    assert_eq!(BitmaskDevice{ width: 128, ..device }.parse_mask(&u128::MAX.to_string()), Some(u128::MAX));
+}
+
+#[test]
+fn should_restart_changed_devices(){
+    let device = |protocol: &str, keys: &str| BitmaskDevice{ protocol: protocol.to_string(), name: String::new(), keys: vec![keys.to_string()], width: 8 };
+    let senders = BitmaskSenders::default();
+    let mut running = vec![];
+    let mut receivers = vec![];
+
+    update_bitmask_devices(&mut running, vec![device("A", "a"), device("B", "b")], &senders, |_, receiver| receivers.push(receiver));
+    update_bitmask_devices(&mut running, vec![device("A", "a"), device("B", "c")], &senders, |_, receiver| receivers.push(receiver));
+
+    assert_eq!(running, vec![device("A", "a"), device("B", "c")]);
+    assert_eq!(receivers.len(), 3);
+    // The old B device was stopped.
+    assert!(matches!(receivers[1].recv(), Err(mpsc::RecvError)));
+    assert!(senders.lock().unwrap().contains_key("B"));
+}
+
+const SECTION_PREFIX: &str = "device ";
+const MAX_WIDTH: u32 = 128;
+
+pub enum BitmaskMessage {
+    Data(ClientId, String),
+    Disconnected(ClientId),
+}
+
+/// Senders of the configured devices by their protocol, shared so devices can change
+/// when the configuration is reloaded.
+pub type BitmaskSenders = Arc<Mutex<HashMap<String, mpsc::Sender<BitmaskMessage>>>>;
+
+/// A virtual device defined in a `[device <PROTOCOL>]` section, that presses its keys
+/// for the set bits of the masks sent with `PROTOCOL|mask`, like `OSU` does.
+///
+/// `keys` lists the keys from bit 0 up, `name` is the name of the device and `width` the
+/// number of bits of the mask, 128 at most.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitmaskDevice<K> {
+    pub protocol: String,
+    pub name: String,
+    pub keys: Vec<K>,
+    pub width: u32,
+}
+
+impl<K> BitmaskDevice<K> {
+    /// Parses a mask, bits above the width are ignored.
+    pub fn parse_mask(&self, data: &str) -> Option<u128>{
+        let mask = data.trim().parse::<u128>().ok()?;
+        match self.width {
+            MAX_WIDTH => Some(mask),
+            width => Some(mask & ((1 << width) - 1)),
+        }
+    }
+}
+
+/// Reads the devices of all `[device <PROTOCOL>]` sections, with `parse` finding the key of
+/// a name. Devices that use a built-in protocol, have an unknown key, no keys or more keys
+/// than bits are skipped.
+pub fn load_bitmask_devices<K>(config: &ConfigFile, parse: impl Fn(&str) -> Option<K>) -> Vec<BitmaskDevice<K>>{
+    let mut devices: Vec<BitmaskDevice<K>> = vec![];
+
+    for section in config.sections() {
+        let protocol = match section.strip_prefix(SECTION_PREFIX) {
+            Some(protocol) => protocol.trim(),
+            None => continue,
+        };
+
+        if Server::is_built_in_protocol(protocol) || devices.iter().any(|device| device.protocol == protocol) {
+            println!("The protocol of [{}] is already in use.", section);
+            continue;
+        }
+
+        let names: Vec<&str> = config.get(section, "keys").unwrap_or("")
+            .split(',')
+            .map(|key| key.trim())
+            .filter(|key| !key.is_empty())
+            .collect();
+        let keys: Option<Vec<K>> = names.iter().map(|name| {
+            let key = parse(name);
+            if key.is_none() {
+                println!("Unknown key in [{}]: {}", section, name);
+            }
+            key
+        }).collect();
+        let keys = match keys {
+            Some(keys) => keys,
+            None => continue,
+        };
+        let width = config.get_or(section, "width", MAX_WIDTH).min(MAX_WIDTH);
+
+        if keys.is_empty() || keys.len() > width as usize {
+            println!("[{}] needs between 1 and {} keys.", section, width);
+            continue;
+        }
+
+        devices.push(BitmaskDevice{
+            protocol: protocol.to_string(),
+            name: config.get(section, "name").unwrap_or(protocol).to_string(),
+            keys,
+            width,
+        });
+    }
+
+    devices
+}
+
+/// Stops the running devices that are gone or changed and starts the new or changed ones
+/// with `start`. A device stops when its sender is dropped.
+pub fn update_bitmask_devices<K: Clone + PartialEq>(running: &mut Vec<BitmaskDevice<K>>, devices: Vec<BitmaskDevice<K>>, senders: &BitmaskSenders, mut start: impl FnMut(BitmaskDevice<K>, mpsc::Receiver<BitmaskMessage>)){
+    let mut senders = senders.lock().unwrap();
+
+    running.retain(|device| {
+        let keep = devices.contains(device);
+        if !keep {
+            senders.remove(&device.protocol);
+        }
+        keep
+    });
+
+    for device in devices {
+        if running.contains(&device) {
+            continue;
+        }
+
+        let (sender, receiver) = mpsc::channel::<BitmaskMessage>();
+        senders.insert(device.protocol.clone(), sender);
+        running.push(device.clone());
+        start(device, receiver);
+    }
+}
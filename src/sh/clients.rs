@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::{IpAddr, TcpStream};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub type ClientId = u32;
+
+/// The connected clients, used to send messages back to them.
+///
+/// Messages use the same `PROTOCOL|data` lines the clients send to the server.
+#[derive(Clone, Default)]
+pub struct Clients {
+    streams: Arc<Mutex<HashMap<ClientId, TcpStream>>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl Clients {
+    /// Registers a new connection and returns the id it is known by.
+    pub fn add(&self, connection: &TcpStream) -> ClientId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(stream) = connection.try_clone() {
+            self.streams.lock().unwrap().insert(id, stream);
+        }
+        id
+    }
+
+    pub fn remove(&self, id: ClientId){
+        self.streams.lock().unwrap().remove(&id);
+    }
+
+    pub fn send(&self, id: ClientId, message: &str){
+        if let Some(stream) = self.streams.lock().unwrap().get_mut(&id) {
+            let _ = writeln!(stream, "{}", message);
+        }
+    }
+
+    pub fn address(&self, id: ClientId) -> Option<IpAddr>{
+        let streams = self.streams.lock().unwrap();
+        streams.get(&id)?.peer_addr().ok().map(|address| address.ip())
+    }
+
+    pub fn broadcast(&self, message: &str){
+        for stream in self.streams.lock().unwrap().values_mut() {
+            let _ = writeln!(stream, "{}", message);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn should_read_values_from_sections(){
+    let config = ConfigFile::parse("top = 1\n# comment\n[mouse]\nsensitivity = 4.5\n\n[keyboard]\nsensitivity=2\n");
+
+    assert_eq!(config.get("", "top"), Some("1"));
+    assert_eq!(config.get("mouse", "sensitivity"), Some("4.5"));
+    assert_eq!(config.get_or("keyboard", "sensitivity", 0u32), 2);
+    assert_eq!(config.get_or("keyboard", "missing", 7u32), 7);
+}
+
+#[test]
+fn should_keep_comments_when_setting_values(){
+    let mut config = ConfigFile::parse("# settings\n[mouse]\n# how fast\nsensitivity = 8\n");
+    config.set("mouse", "sensitivity", "6");
+    config.set("mouse", "invert_y", "1");
+    config.set("osu", "mode", "taiko");
+
+    assert_eq!(config.to_string(), "# settings\n[mouse]\n# how fast\nsensitivity = 6\ninvert_y = 1\n\n[osu]\nmode = taiko\n");
+}
+
+#[test]
+fn should_count_reloads_that_change_something(){
+    let path = std::env::temp_dir().join(format!("sensorhandler-reload-{}.conf", std::process::id()));
+    fs::write(&path, "[mouse]\nsensitivity = 8\n").unwrap();
+
+    let mut config = ConfigFile::load(&path).unwrap();
+    assert!(!config.reload().unwrap());
+
+    fs::write(&path, "[mouse]\nsensitivity = 4\n").unwrap();
+    assert!(config.reload().unwrap());
+    assert_eq!(config.get("mouse", "sensitivity"), Some("4"));
+    assert_eq!(config.generation(), 1);
+
+    let _ = fs::remove_file(&path);
+}
+
+/// Shared handle to the configuration, for threads that need to persist their settings.
+pub type SharedConfig = Arc<Mutex<ConfigFile>>;
+
+#[derive(Debug, Clone)]
+enum Line {
+    Section(String),
+    Entry(String, String),
+    Other(String),
+}
+
+/// An INI style configuration file.
+///
+/// Values live in `key = value` lines grouped under `[section]` headers, lines starting
+/// with `#` or `;` are comments. The original lines are kept, so writing the file back
+/// only touches the values that were changed.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFile {
+    path: Option<PathBuf>,
+    lines: Vec<(Line, Option<String>)>,
+    generation: u64,
+}
+
+impl ConfigFile {
+    /// Loads the file at `path`, a missing file gives an empty configuration.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+
+        let mut config = Self::parse(&text);
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let lines = text.lines().map(|raw| {
+            let trimmed = raw.trim();
+
+            let line = if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                Line::Section(trimmed[1..trimmed.len() - 1].trim().to_string())
+            } else if trimmed.starts_with('#') || trimmed.starts_with(';') {
+                Line::Other(raw.to_string())
+            } else if let Some((key, value)) = trimmed.split_once('=') {
+                Line::Entry(key.trim().to_string(), value.trim().to_string())
+            } else {
+                Line::Other(raw.to_string())
+            };
+
+            (line, Some(raw.to_string()))
+        }).collect();
+
+        Self{ path: None, lines, generation: 0 }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Reads the file again, returns whether anything changed. Writing settings back
+    /// does not count as a change, the file then holds what is already loaded.
+    pub fn reload(&mut self) -> io::Result<bool> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return Ok(false),
+        };
+
+        let loaded = ConfigFile::load(&path)?;
+        if loaded.to_string() == self.to_string() {
+            return Ok(false);
+        }
+
+        self.lines = loaded.lines;
+        self.generation += 1;
+        Ok(true)
+    }
+
+    /// How many times a reload changed the configuration, threads compare it to know
+    /// when to read their settings again.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Writes the configuration back to the file it was loaded from.
+    pub fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => fs::write(path, self.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.entries(section).into_iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Parses the value of `key`, falling back to `default` when it is missing or malformed.
+    pub fn get_or<T: FromStr>(&self, section: &str, key: &str, default: T) -> T {
+        self.get(section, key)
+            .and_then(|value| value.parse::<T>().ok())
+            .unwrap_or(default)
+    }
+
+    pub fn get_bool_or(&self, section: &str, key: &str, default: bool) -> bool {
+        self.get(section, key).and_then(parse_bool).unwrap_or(default)
+    }
+
+    /// All `key = value` pairs of a section in file order. The unnamed section holds
+    /// the entries written before the first header.
+    pub fn entries(&self, section: &str) -> Vec<(&str, &str)> {
+        let mut current = "";
+        let mut entries = vec![];
+
+        for (line, _) in &self.lines {
+            match line {
+                Line::Section(name) => current = name,
+                Line::Entry(key, value) if current == section => entries.push((key.as_str(), value.as_str())),
+                _ => {}
+            }
+        }
+
+        entries
+    }
+
+    /// Names of all sections in file order.
+    pub fn sections(&self) -> Vec<&str> {
+        self.lines.iter()
+            .filter_map(|(line, _)| match line {
+                Line::Section(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sets `key` in `section`, adding the key or the whole section when needed.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let mut current = "";
+        let mut section_end = if section.is_empty() { Some(0) } else { None };
+
+        for (index, (line, raw)) in self.lines.iter_mut().enumerate() {
+            match line {
+                Line::Section(name) => {
+                    current = name;
+                    if current == section {
+                        section_end = Some(index + 1);
+                    }
+                }
+                Line::Entry(name, old) if current == section && name == key => {
+                    *old = value.to_string();
+                    *raw = None;
+                    return;
+                }
+                Line::Entry(..) if current == section => section_end = Some(index + 1),
+                _ => {}
+            }
+        }
+
+        let entry = (Line::Entry(key.to_string(), value.to_string()), None);
+
+        match section_end {
+            Some(index) => self.lines.insert(index, entry),
+            None => {
+                if self.lines.last().is_some_and(|(line, _)| !matches!(line, Line::Other(raw) if raw.trim().is_empty())) {
+                    self.lines.push((Line::Other(String::new()), None));
+                }
+                self.lines.push((Line::Section(section.to_string()), None));
+                self.lines.push(entry);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (line, raw) in &self.lines {
+            match (raw, line) {
+                (Some(raw), _) => writeln!(f, "{}", raw)?,
+                (None, Line::Section(name)) => writeln!(f, "[{}]", name)?,
+                (None, Line::Entry(key, value)) => writeln!(f, "{} = {}", key, value)?,
+                (None, Line::Other(raw)) => writeln!(f, "{}", raw)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Accepts the usual spellings of a flag: `1`/`0`, `true`/`false`, `on`/`off`, `yes`/`no`.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Some(true),
+        "0" | "false" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::config_file::{ConfigFile, SharedConfig};
+
+// How often the modification time of the file is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads the configuration when its file changes or `reload_requested` gets set, e.g.
+/// on SIGHUP, and then calls `on_reload` with the new configuration.
+///
+/// The device threads notice the reload through `ReloadTracker`, clients stay connected.
+pub fn start_config_watcher(config: SharedConfig, reload_requested: &'static AtomicBool, mut on_reload: impl FnMut(&ConfigFile)){
+    let modified = |config: &SharedConfig| -> Option<SystemTime> {
+        let path = config.lock().unwrap().path()?.to_path_buf();
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    };
+    let mut last_modified = modified(&config);
+
+    loop{
+        thread::sleep(POLL_INTERVAL);
+
+        let now_modified = modified(&config);
+        if !reload_requested.swap(false, Ordering::SeqCst) && now_modified == last_modified {
+            continue;
+        }
+        last_modified = now_modified;
+
+        let mut config = config.lock().unwrap();
+        match config.reload() {
+            Ok(true) => {
+                println!("Configuration reloaded.");
+                on_reload(&config);
+            }
+            Ok(false) => {}
+            Err(error) => {
+                println!("Failed to reload the configuration, keeping the old one.");
+                println!("{}", error);
+            }
+        }
+    }
+}
+
+/// Tells a device thread when the configuration was reloaded, so it can read its settings again.
+#[derive(Debug)]
+pub struct ReloadTracker {
+    seen: u64,
+}
+
+impl ReloadTracker {
+    pub fn new(config: &SharedConfig) -> Self {
+        Self{ seen: config.lock().unwrap().generation() }
+    }
+
+    /// Whether the configuration changed since the last call.
+    pub fn reloaded(&mut self, config: &SharedConfig) -> bool {
+        let generation = config.lock().unwrap().generation();
+        let reloaded = generation != self.seen;
+        self.seen = generation;
+        reloaded
+    }
+}
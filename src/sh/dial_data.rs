@@ -0,0 +1,33 @@
+#[test]
+fn should_parse_dial_data(){
+    let data = DialData::new("-3;1;1".to_string());
+
+    assert_eq!(data.rotation, -3);
+    assert_eq!(data.misc, 1);
+    assert_eq!(data.button_state, 1);
+
+    assert_eq!(DialData::new("2".to_string()).misc, 0);
+}
+
+/// Data of the `DIAL` protocol: `rotation;misc;buttons`
+///
+/// `rotation` is the number of detents turned since the last message, clockwise being
+/// positive. `misc` is a second relative value for dials with an extra ring, and bit 0 of
+/// the buttons is pressing the dial down.
+pub struct DialData {
+    pub rotation: i32,
+    pub misc: i32,
+    pub button_state: u8,
+}
+
+impl DialData {
+    pub fn new(data_string: String) -> Self {
+
+        let mut split = data_string.split(';');
+        let rotation = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).round() as i32;
+        let misc = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).round() as i32;
+        let buttons = split.next().unwrap_or("0").parse::<u8>().unwrap_or(0u8);
+
+        Self{ rotation, misc, button_state: buttons }
+    }
+}
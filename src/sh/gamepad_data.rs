@@ -0,0 +1,102 @@
+use super::clients::ClientId;
+
+#[test]
+fn should_parse_gamepad_data(){
+    let data = GamepadData::new("0.5;-1;0;2;0.25;1;-1;1;513".to_string());
+
+    assert_eq!(data.left_x, 0.5);
+    assert_eq!(data.left_y, -1.0);
+    assert_eq!(data.right_y, 1.0);
+    assert_eq!(data.left_trigger, 0.25);
+    assert_eq!(data.hat_x, -1);
+    assert_eq!(data.hat_y, 1);
+    assert_eq!(data.button_state, 0b10_0000_0001);
+}
+
+/// Data of the `GAMEPAD` protocol:
+/// `left_x;left_y;right_x;right_y;left_trigger;right_trigger;hat_x;hat_y;buttons`
+///
+/// Sticks are in `-1.0..=1.0` with up being positive, triggers in `0.0..=1.0` and the
+/// D-pad hat in `-1..=1`. Button bits follow the Xbox layout: south, east, north, west,
+/// left/right shoulder, select, start, mode, left/right thumb.
+#[derive(Debug, Clone, Default)]
+pub struct GamepadData {
+    pub left_x: f32,
+    pub left_y: f32,
+    pub right_x: f32,
+    pub right_y: f32,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    pub hat_x: i32,
+    pub hat_y: i32,
+    pub button_state: u16,
+}
+
+impl GamepadData {
+    pub fn new(data_string: String) -> Self {
+
+        let mut split = data_string.split(';');
+        let mut next_f32 = |min: f32| split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(min, 1.0);
+
+        let left_x = next_f32(-1.0);
+        let left_y = next_f32(-1.0);
+        let right_x = next_f32(-1.0);
+        let right_y = next_f32(-1.0);
+        let left_trigger = next_f32(0.0);
+        let right_trigger = next_f32(0.0);
+        let hat_x = next_f32(-1.0).round() as i32;
+        let hat_y = next_f32(-1.0).round() as i32;
+        let buttons = split.next().unwrap_or("0").parse::<u16>().unwrap_or(0u16);
+
+        Self{ left_x, left_y, right_x, right_y, left_trigger, right_trigger, hat_x, hat_y, button_state: buttons }
+    }
+
+    /// Adds `value` to an analog axis, keeping it inside the axis range.
+    pub fn add_to_axis(&mut self, axis: GamepadAxis, value: f32){
+        let (current, min) = match axis {
+            GamepadAxis::LeftX => (&mut self.left_x, -1.0),
+            GamepadAxis::LeftY => (&mut self.left_y, -1.0),
+            GamepadAxis::RightX => (&mut self.right_x, -1.0),
+            GamepadAxis::RightY => (&mut self.right_y, -1.0),
+            GamepadAxis::LeftTrigger => (&mut self.left_trigger, 0.0),
+            GamepadAxis::RightTrigger => (&mut self.right_trigger, 0.0),
+        };
+        *current = (*current + value).clamp(min, 1.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    pub fn from_name(name: &str) -> Option<GamepadAxis>{
+        match name {
+            "left_x" => Some(GamepadAxis::LeftX),
+            "left_y" => Some(GamepadAxis::LeftY),
+            "right_x" => Some(GamepadAxis::RightX),
+            "right_y" => Some(GamepadAxis::RightY),
+            "left_trigger" => Some(GamepadAxis::LeftTrigger),
+            "right_trigger" => Some(GamepadAxis::RightTrigger),
+            _ => None,
+        }
+    }
+}
+
+/// Messages handled by a gamepad thread, tagged with the client that sent them.
+pub enum GamepadMessage {
+    /// Data of the `GAMEPAD` protocol.
+    Data(ClientId, String),
+    /// Data of the `TILT` protocol, see `TiltMapper`.
+    Tilt(ClientId, String),
+    /// Data of the `PLAYER` protocol, the player slot to claim or `any`.
+    Claim(ClientId, String),
+    /// The client disconnected, its player slot is free again.
+    Disconnected(ClientId),
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::clients::ClientId;
+use super::config_file::SharedConfig;
+use super::config_watcher::ReloadTracker;
+use super::gesture_recognizer::{gesture_actions, GestureAction, GestureRecognizer, StrokeMessage};
+use super::keyboard_data::KeyboardMessage;
+use super::mouse_data::MouseMessage;
+use super::touch_data::TouchData;
+
+// How often a held finger is checked for a long press while no frames arrive.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Recognises gestures in `STROKE` frames and fires the action configured for them in the
+/// `[gestures]` section. Frames have the format of the `TOUCHPAD` protocol, the button
+/// state is ignored. Every client draws its own gestures.
+pub fn start_gesture_input(receiver: mpsc::Receiver<StrokeMessage>, keyboard_sender: mpsc::Sender<KeyboardMessage>, mouse_sender: mpsc::Sender<MouseMessage>, config: SharedConfig){
+
+    let mut actions = gesture_actions(&config.lock().unwrap());
+    let mut reloads = ReloadTracker::new(&config);
+    let mut recognizers: HashMap<ClientId, GestureRecognizer> = HashMap::new();
+    let started = Instant::now();
+
+    loop{
+        if reloads.reloaded(&config) {
+            actions = gesture_actions(&config.lock().unwrap());
+        }
+
+        let time = started.elapsed().as_secs_f64();
+        let mut gestures = vec![];
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(StrokeMessage::Frame(client, message)) => {
+                let recognizer = recognizers.entry(client).or_default();
+                gestures.extend(recognizer.update(&TouchData::new(message).contacts, time));
+            }
+            Ok(StrokeMessage::Disconnected(client)) => {
+                recognizers.remove(&client);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        // Fingers held by the other clients are checked for a long press too.
+        gestures.extend(recognizers.values_mut().filter_map(|recognizer| recognizer.tick(time)));
+
+        for action in gestures.iter().filter_map(|gesture| actions.get(gesture)) {
+            match action {
+                GestureAction::Keys(keys) => {
+                    let _ = keyboard_sender.send(KeyboardMessage::Chord(*keys));
+                }
+                GestureAction::Mouse{ modifiers, wheel, buttons } => {
+                    let _ = mouse_sender.send(MouseMessage::Action{ modifiers: *modifiers, wheel: *wheel, buttons: *buttons });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use super::clients::ClientId;
+use super::config_file::ConfigFile;
+use super::key_names::key_bit;
+use super::touch_data::TouchContact;
+
+#[test]
+fn should_recognise_swipes_and_circles(){
+    let mut recognizer = GestureRecognizer::default();
+
+    for step in 0..=10 {
+        assert!(recognizer.update(&[contact(1, 0.8 - step as f32 * 0.05, 0.5)], step as f64 * 0.01).is_none());
+    }
+    assert_eq!(recognizer.update(&[], 0.2), Some(Gesture::SwipeLeft));
+
+    // Clockwise on screen, y grows downwards.
+    for step in 0..=20 {
+        let angle = step as f32 / 20.0 * 2.0 * PI;
+        recognizer.update(&[contact(2, 0.5 + 0.2 * angle.cos(), 0.5 + 0.2 * angle.sin())], step as f64 * 0.01);
+    }
+    assert_eq!(recognizer.update(&[], 0.3), Some(Gesture::CircleClockwise));
+}
+
+#[test]
+fn should_recognise_pinch_and_long_press(){
+    let mut recognizer = GestureRecognizer::default();
+
+    recognizer.update(&[contact(1, 0.4, 0.5), contact(2, 0.6, 0.5)], 0.0);
+    assert_eq!(recognizer.update(&[contact(1, 0.35, 0.5), contact(2, 0.65, 0.5)], 0.1), Some(Gesture::PinchOut));
+    assert_eq!(recognizer.update(&[contact(1, 0.45, 0.5), contact(2, 0.55, 0.5)], 0.2), Some(Gesture::PinchIn));
+    assert!(recognizer.update(&[], 0.3).is_none());
+
+    recognizer.update(&[contact(3, 0.5, 0.5)], 1.0);
+    assert!(recognizer.tick(1.2).is_none());
+    assert_eq!(recognizer.tick(1.7), Some(Gesture::LongPress));
+    assert!(recognizer.tick(1.8).is_none());
+    assert!(recognizer.update(&[], 2.0).is_none());
+}
+
+#[test]
+fn should_parse_gesture_actions(){
+    assert_eq!(GestureAction::parse("alt+left"), Some(GestureAction::Keys(1 << 53 | 1 << 62)));
+    assert_eq!(GestureAction::parse("ctrl+wheel_up"), Some(GestureAction::Mouse{ modifiers: 1 << 55, wheel: 1, buttons: 0 }));
+    assert_eq!(GestureAction::parse("right_click"), Some(GestureAction::Mouse{ modifiers: 0, wheel: 0, buttons: 0b10 }));
+    assert_eq!(GestureAction::parse("ctrl+nope"), None);
+}
+
+#[cfg(test)]
+fn contact(id: u32, x: f32, y: f32) -> TouchContact {
+    TouchContact{ id, x, y }
+}
+
+const SECTION: &str = "gestures";
+
+// Distances are in the normalized touch coordinates.
+const MIN_STROKE_LENGTH: f32 = 0.1;
+const SWIPE_MIN_DISTANCE: f32 = 0.15;
+// Share of the stroke length that has to go in a straight line for a swipe.
+const SWIPE_STRAIGHTNESS: f32 = 0.8;
+// A circle has to turn at least this far and end close to where it started.
+const CIRCLE_MIN_TURN: f32 = 1.6 * PI;
+const CIRCLE_MAX_GAP: f32 = 0.35;
+// Change of the finger distance that fires one pinch step.
+const PINCH_STEP: f32 = 0.2;
+const LONG_PRESS_TIME: f64 = 0.6;
+const LONG_PRESS_SLOP: f32 = 0.03;
+
+/// Messages handled by the gesture thread, tagged with the client that sent them.
+pub enum StrokeMessage {
+    /// A frame of the `STROKE` protocol.
+    Frame(ClientId, String),
+    /// The client disconnected, a gesture it was drawing is dropped.
+    Disconnected(ClientId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gesture {
+    SwipeLeft,
+    SwipeRight,
+    SwipeUp,
+    SwipeDown,
+    CircleClockwise,
+    CircleCounterClockwise,
+    PinchIn,
+    PinchOut,
+    LongPress,
+}
+
+impl Gesture {
+    const ALL: [Gesture; 9] = [
+        Gesture::SwipeLeft,
+        Gesture::SwipeRight,
+        Gesture::SwipeUp,
+        Gesture::SwipeDown,
+        Gesture::CircleClockwise,
+        Gesture::CircleCounterClockwise,
+        Gesture::PinchIn,
+        Gesture::PinchOut,
+        Gesture::LongPress,
+    ];
+
+    /// Name of the gesture in the `[gestures]` section.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gesture::SwipeLeft => "swipe_left",
+            Gesture::SwipeRight => "swipe_right",
+            Gesture::SwipeUp => "swipe_up",
+            Gesture::SwipeDown => "swipe_down",
+            Gesture::CircleClockwise => "circle_cw",
+            Gesture::CircleCounterClockwise => "circle_ccw",
+            Gesture::PinchIn => "pinch_in",
+            Gesture::PinchOut => "pinch_out",
+            Gesture::LongPress => "long_press",
+        }
+    }
+}
+
+/// What a recognised gesture does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GestureAction {
+    /// A key chord tapped on the virtual keyboard, as a `KEYBOARD` key mask.
+    Keys(u128),
+    /// Mouse wheel steps and button clicks, with modifier keys held down around them.
+    Mouse{ modifiers: u128, wheel: i32, buttons: u8 },
+}
+
+impl GestureAction {
+    /// Parses keys and mouse actions joined with `+`, like `alt+left` or `ctrl+wheel_up`.
+    /// The mouse actions are `wheel_up`, `wheel_down`, `left_click`, `right_click` and
+    /// `middle_click`.
+    pub fn parse(action: &str) -> Option<GestureAction>{
+        let mut keys = 0u128;
+        let mut wheel = 0;
+        let mut buttons = 0u8;
+        let mut mouse = false;
+
+        for name in action.split('+').map(str::trim) {
+            match name {
+                "wheel_up" => wheel += 1,
+                "wheel_down" => wheel -= 1,
+                "left_click" => buttons |= 0b001,
+                "right_click" => buttons |= 0b010,
+                "middle_click" => buttons |= 0b100,
+                name => {
+                    keys |= 1 << key_bit(name)?;
+                    continue;
+                }
+            }
+            mouse = true;
+        }
+
+        match mouse {
+            true => Some(GestureAction::Mouse{ modifiers: keys, wheel, buttons }),
+            false => Some(GestureAction::Keys(keys)),
+        }
+    }
+}
+
+/// The action of every gesture, read from the `[gestures]` section.
+pub fn gesture_actions(config: &ConfigFile) -> HashMap<Gesture, GestureAction>{
+    let defaults = [
+        (Gesture::SwipeLeft, "alt+left"),
+        (Gesture::SwipeRight, "alt+right"),
+        (Gesture::PinchIn, "ctrl+wheel_down"),
+        (Gesture::PinchOut, "ctrl+wheel_up"),
+        (Gesture::LongPress, "right_click"),
+    ];
+
+    let mut actions = HashMap::new();
+    for gesture in Gesture::ALL {
+        let default = defaults.iter().find(|(default, _)| *default == gesture).map(|(_, action)| *action);
+        let action = match config.get(SECTION, gesture.name()).or(default) {
+            Some(action) => action,
+            None => continue,
+        };
+
+        match GestureAction::parse(action) {
+            Some(action) => { actions.insert(gesture, action); }
+            None => println!("Unknown action for gesture {}: {}", gesture.name(), action),
+        }
+    }
+    actions
+}
+
+/// Recognises gestures in the touch frames sent with the `STROKE` protocol.
+///
+/// Swipes and circles are drawn with one finger and recognised when it is lifted. Pinches
+/// fire a step every time the distance of two fingers changes by a fifth, so a long pinch
+/// zooms in several steps. A long press fires while the finger is still held.
+#[derive(Debug, Default)]
+pub struct GestureRecognizer {
+    primary: Option<u32>,
+    started: f64,
+    path: Vec<(f32, f32)>,
+    max_contacts: usize,
+    pinch_distance: Option<f32>,
+    long_pressed: bool,
+}
+
+impl GestureRecognizer {
+    /// Handles a frame with all contacts currently on the screen, taken at `time` seconds.
+    pub fn update(&mut self, contacts: &[TouchContact], time: f64) -> Option<Gesture>{
+        if contacts.is_empty() {
+            self.primary?;
+            let gesture = match self.max_contacts == 1 && !self.long_pressed {
+                true => self.classify_path(),
+                false => None,
+            };
+            *self = Self::default();
+            return gesture;
+        }
+
+        if self.primary.is_none() {
+            self.primary = Some(contacts[0].id);
+            self.started = time;
+        }
+        self.max_contacts = self.max_contacts.max(contacts.len());
+
+        if let Some(contact) = contacts.iter().find(|contact| Some(contact.id) == self.primary) {
+            self.path.push((contact.x, contact.y));
+        }
+
+        if contacts.len() < 2 {
+            self.pinch_distance = None;
+            return self.tick(time);
+        }
+
+        let distance = (contacts[0].x - contacts[1].x).hypot(contacts[0].y - contacts[1].y);
+        let reference = *self.pinch_distance.get_or_insert(distance);
+        if distance >= reference * (1.0 + PINCH_STEP) {
+            self.pinch_distance = Some(distance);
+            return Some(Gesture::PinchOut);
+        }
+        if distance <= reference / (1.0 + PINCH_STEP) {
+            self.pinch_distance = Some(distance);
+            return Some(Gesture::PinchIn);
+        }
+        None
+    }
+
+    /// Checks for a long press, also called in between frames.
+    pub fn tick(&mut self, time: f64) -> Option<Gesture>{
+        self.primary?;
+        if self.max_contacts != 1 || self.long_pressed || time - self.started < LONG_PRESS_TIME {
+            return None;
+        }
+
+        let start = self.path[0];
+        let wandered = self.path.iter().any(|(x, y)| (x - start.0).hypot(y - start.1) > LONG_PRESS_SLOP);
+        if wandered {
+            return None;
+        }
+
+        self.long_pressed = true;
+        Some(Gesture::LongPress)
+    }
+
+    fn classify_path(&self) -> Option<Gesture>{
+        let start = *self.path.first()?;
+        let end = *self.path.last()?;
+        let (delta_x, delta_y) = (end.0 - start.0, end.1 - start.1);
+        let distance = delta_x.hypot(delta_y);
+
+        let segments: Vec<(f32, f32)> = self.path.windows(2)
+            .map(|points| (points[1].0 - points[0].0, points[1].1 - points[0].1))
+            .filter(|(x, y)| *x != 0.0 || *y != 0.0)
+            .collect();
+        let length: f32 = segments.iter().map(|(x, y)| x.hypot(*y)).sum();
+
+        if length < MIN_STROKE_LENGTH {
+            return None;
+        }
+
+        if distance >= SWIPE_MIN_DISTANCE && distance / length >= SWIPE_STRAIGHTNESS {
+            return Some(match (delta_x.abs() > delta_y.abs(), delta_x > 0.0, delta_y > 0.0) {
+                (true, true, _) => Gesture::SwipeRight,
+                (true, false, _) => Gesture::SwipeLeft,
+                (false, _, true) => Gesture::SwipeDown,
+                (false, _, false) => Gesture::SwipeUp,
+            });
+        }
+
+        // Sum of the signed turns between segments, positive is clockwise on screen.
+        let turn: f32 = segments.windows(2)
+            .map(|pair| {
+                let (a, b) = (pair[0], pair[1]);
+                (a.0 * b.1 - a.1 * b.0).atan2(a.0 * b.0 + a.1 * b.1)
+            })
+            .sum();
+
+        if turn.abs() >= CIRCLE_MIN_TURN && distance <= length * CIRCLE_MAX_GAP {
+            return Some(match turn > 0.0 {
+                true => Gesture::CircleClockwise,
+                false => Gesture::CircleCounterClockwise,
+            });
+        }
+
+        None
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_tap_or_hold_dual_role_keys(){
+    let mut behaviors = KeyBehaviors::from_config(&ConfigFile::parse("[tap_hold]\nspace = leftshift\n"), parse_name);
+
+    assert!(behaviors.event("space", 1, 0.0).is_empty());
+    assert_eq!(behaviors.event("space", 0, 0.1), vec![("space", 1), ("space", 0)]);
+
+    behaviors.event("space", 1, 1.0);
+    assert_eq!(behaviors.next_due(), Some(1.2));
+    assert_eq!(behaviors.tick(1.2), vec![("leftshift", 1)]);
+    assert_eq!(behaviors.event("space", 0, 1.5), vec![("leftshift", 0)]);
+
+    // Another key pressed while undecided makes it a hold.
+    behaviors.event("space", 1, 2.0);
+    assert_eq!(behaviors.event("a", 1, 2.05), vec![("leftshift", 1), ("a", 1)]);
+}
+
+#[test]
+fn should_send_chords(){
+    let mut behaviors = KeyBehaviors::from_config(&ConfigFile::parse("[chords]\nj+k = esc\n"), parse_name);
+
+    assert!(behaviors.event("j", 1, 0.0).is_empty());
+    assert_eq!(behaviors.event("k", 1, 0.02), vec![("esc", 1)]);
+    assert_eq!(behaviors.event("j", 0, 0.1), vec![("esc", 0)]);
+    assert!(behaviors.event("k", 0, 0.1).is_empty());
+
+    // Too slow for a chord, the key is pressed when the window closes.
+    behaviors.event("j", 1, 1.0);
+    assert_eq!(behaviors.tick(1.05), vec![("j", 1)]);
+    assert!(behaviors.event("k", 1, 1.1).is_empty());
+    assert_eq!(behaviors.event("k", 0, 1.12), vec![("k", 1), ("k", 0)]);
+}
+
+#[test]
+fn should_hold_one_shot_modifiers_for_next_key(){
+    let mut behaviors = KeyBehaviors::from_config(&ConfigFile::parse("[one_shot]\nkeys = leftshift\n"), parse_name);
+
+    assert_eq!(behaviors.event("leftshift", 1, 0.0), vec![("leftshift", 1)]);
+    assert!(behaviors.event("leftshift", 0, 0.1).is_empty());
+    assert_eq!(behaviors.event("a", 1, 0.5), vec![("a", 1), ("leftshift", 0)]);
+    assert_eq!(behaviors.event("a", 0, 0.6), vec![("a", 0)]);
+
+    // Held while another key is pressed, it is an ordinary modifier.
+    behaviors.event("leftshift", 1, 1.0);
+    behaviors.event("a", 1, 1.1);
+    assert_eq!(behaviors.event("leftshift", 0, 1.2), vec![("leftshift", 0)]);
+}
+
+#[cfg(test)]
+fn parse_name(name: &str) -> Option<&'static str> {
+    ["space", "leftshift", "a", "j", "k", "esc"].into_iter().find(|key| *key == name)
+}
+
+const TAP_HOLD_SECTION: &str = "tap_hold";
+const CHORDS_SECTION: &str = "chords";
+const ONE_SHOT_SECTION: &str = "one_shot";
+
+const DEFAULT_TAP_HOLD_TIMEOUT: f64 = 200.0;
+const DEFAULT_CHORD_TIMEOUT: f64 = 50.0;
+
+/// Key events from the edge detection, as `(key, state)` with state 1 for a press and 0
+/// for a release.
+type KeyEvents<K> = Vec<(K, i32)>;
+
+/// Keys that act as one key on tap and another one when held, e.g. `space = leftshift`.
+#[derive(Debug)]
+struct TapHold<K> {
+    keys: HashMap<K, K>,
+    timeout: f64,
+    // The key still waiting to be decided, with the time it was pressed.
+    pending: Option<(K, f64)>,
+    holding: HashSet<K>,
+}
+
+impl<K: Copy + Eq + Hash> TapHold<K> {
+    fn event(&mut self, key: K, state: i32, time: f64, events: &mut KeyEvents<K>){
+        let pending = self.pending.map(|(pending, _)| pending) == Some(key);
+
+        match state {
+            1 => {
+                self.resolve(events);
+                match self.keys.contains_key(&key) {
+                    true => self.pending = Some((key, time)),
+                    false => events.push((key, 1)),
+                }
+            }
+            0 if pending => {
+                self.pending = None;
+                events.extend([(key, 1), (key, 0)]);
+            }
+            0 if self.holding.remove(&key) => events.push((self.keys[&key], 0)),
+            _ if pending => {}
+            _ if self.holding.contains(&key) => events.push((self.keys[&key], state)),
+            _ => events.push((key, state)),
+        }
+    }
+
+    /// Decides the pending key as held.
+    fn resolve(&mut self, events: &mut KeyEvents<K>){
+        if let Some((key, _)) = self.pending.take() {
+            self.holding.insert(key);
+            events.push((self.keys[&key], 1));
+        }
+    }
+
+    fn tick(&mut self, time: f64, events: &mut KeyEvents<K>){
+        if self.next_due().map(|due| time >= due).unwrap_or(false) {
+            self.resolve(events);
+        }
+    }
+
+    fn next_due(&self) -> Option<f64>{
+        self.pending.map(|(_, pressed)| pressed + self.timeout)
+    }
+}
+
+/// Keys pressed together within the timeout that send another key, e.g. `j+k = esc`.
+#[derive(Debug)]
+struct Chords<K> {
+    chords: Vec<(HashSet<K>, K)>,
+    timeout: f64,
+    // Presses held back while they may still become a chord.
+    buffer: Vec<(K, f64)>,
+    // Members of sent chords that are still held, the chord is released with the first of them.
+    active: Vec<(HashSet<K>, K, bool)>,
+}
+
+impl<K: Copy + Eq + Hash> Chords<K> {
+    fn event(&mut self, key: K, state: i32, time: f64, events: &mut KeyEvents<K>){
+        let buffered = self.buffer.iter().any(|(buffered, _)| *buffered == key);
+        let active = self.active.iter().position(|(members, _, _)| members.contains(&key));
+
+        match state {
+            1 => {
+                let mut candidate: HashSet<K> = self.buffer.iter().map(|(buffered, _)| *buffered).collect();
+                candidate.insert(key);
+
+                if !self.chords.iter().any(|(members, _)| candidate.is_subset(members)) {
+                    self.flush(events);
+                    candidate = HashSet::from([key]);
+                    if !self.chords.iter().any(|(members, _)| candidate.is_subset(members)) {
+                        events.push((key, 1));
+                        return;
+                    }
+                }
+
+                self.buffer.push((key, time));
+                if let Some((members, output)) = self.chords.iter().find(|(members, _)| *members == candidate) {
+                    self.buffer.clear();
+                    self.active.push((members.clone(), *output, false));
+                    events.push((*output, 1));
+                }
+            }
+            0 => match active {
+                Some(index) => {
+                    let (members, output, released) = &mut self.active[index];
+                    members.remove(&key);
+                    if !*released {
+                        *released = true;
+                        events.push((*output, 0));
+                    }
+                    if members.is_empty() {
+                        self.active.remove(index);
+                    }
+                }
+                None => {
+                    if buffered {
+                        self.flush(events);
+                    }
+                    events.push((key, 0));
+                }
+            },
+            _ if buffered || active.is_some() => {}
+            _ => events.push((key, state)),
+        }
+    }
+
+    fn flush(&mut self, events: &mut KeyEvents<K>){
+        events.extend(self.buffer.drain(..).map(|(key, _)| (key, 1)));
+    }
+
+    fn tick(&mut self, time: f64, events: &mut KeyEvents<K>){
+        if self.next_due().map(|due| time >= due).unwrap_or(false) {
+            self.flush(events);
+        }
+    }
+
+    fn next_due(&self) -> Option<f64>{
+        self.buffer.first().map(|(_, pressed)| pressed + self.timeout)
+    }
+}
+
+/// Modifiers that stay pressed for the next key when tapped on their own.
+#[derive(Debug)]
+struct OneShot<K> {
+    keys: HashSet<K>,
+    // Held one-shot keys, and whether another key was pressed meanwhile.
+    held: HashMap<K, bool>,
+    armed: Vec<K>,
+}
+
+impl<K: Copy + Eq + Hash> OneShot<K> {
+    fn event(&mut self, key: K, state: i32, events: &mut KeyEvents<K>){
+        if !self.keys.contains(&key) {
+            events.push((key, state));
+            if state == 1 {
+                self.held.values_mut().for_each(|used| *used = true);
+                events.extend(self.armed.drain(..).map(|armed| (armed, 0)));
+            }
+            return;
+        }
+
+        match state {
+            // Tapping an armed key again takes it back, it is released as usual.
+            1 if self.armed.contains(&key) => {
+                self.armed.retain(|armed| *armed != key);
+                self.held.insert(key, true);
+            }
+            1 => {
+                self.held.insert(key, false);
+                events.push((key, 1));
+            }
+            0 => match self.held.remove(&key) {
+                Some(false) => self.armed.push(key),
+                _ => events.push((key, 0)),
+            },
+            _ => events.push((key, state)),
+        }
+    }
+}
+
+/// Tap-hold keys, chords and one-shot modifiers, between the decoded key events and the
+/// device.
+///
+/// Unlike the edge detection these depend on timing, so besides handling events `tick`
+/// has to be called until `next_due` passes. Time is passed in seconds. Chords are
+/// recognised first, their keys can then be tap-hold or one-shot keys.
+#[derive(Debug)]
+pub struct KeyBehaviors<K> {
+    chords: Chords<K>,
+    tap_hold: TapHold<K>,
+    one_shot: OneShot<K>,
+}
+
+impl<K: Copy + Eq + Hash> KeyBehaviors<K> {
+    /// Reads the `[tap_hold]`, `[chords]` and `[one_shot]` sections, keys are named as in
+    /// the config and turned into device keys by `parse`. Timeouts are in milliseconds.
+    pub fn from_config(config: &ConfigFile, parse: impl Fn(&str) -> Option<K>) -> Self {
+        let parse = |section: &str, name: &str| {
+            let key = parse(name.trim());
+            if key.is_none() {
+                println!("Unknown key in [{}]: {}", section, name);
+            }
+            key
+        };
+
+        let mut tap_hold = HashMap::new();
+        for (tap, hold) in config.entries(TAP_HOLD_SECTION) {
+            if tap == "timeout" {
+                continue;
+            }
+            if let (Some(tap), Some(hold)) = (parse(TAP_HOLD_SECTION, tap), parse(TAP_HOLD_SECTION, hold)) {
+                tap_hold.insert(tap, hold);
+            }
+        }
+
+        let mut chords = vec![];
+        for (members, output) in config.entries(CHORDS_SECTION) {
+            if members == "timeout" {
+                continue;
+            }
+            let members: Option<HashSet<K>> = members.split('+').map(|member| parse(CHORDS_SECTION, member)).collect();
+            if let (Some(members), Some(output)) = (members, parse(CHORDS_SECTION, output)) {
+                chords.push((members, output));
+            }
+        }
+
+        let one_shot = config.get(ONE_SHOT_SECTION, "keys").unwrap_or("")
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .filter_map(|name| parse(ONE_SHOT_SECTION, name))
+            .collect();
+
+        Self{
+            chords: Chords{
+                chords,
+                timeout: config.get_or(CHORDS_SECTION, "timeout", DEFAULT_CHORD_TIMEOUT) / 1000.0,
+                buffer: vec![],
+                active: vec![],
+            },
+            tap_hold: TapHold{
+                keys: tap_hold,
+                timeout: config.get_or(TAP_HOLD_SECTION, "timeout", DEFAULT_TAP_HOLD_TIMEOUT) / 1000.0,
+                pending: None,
+                holding: HashSet::new(),
+            },
+            one_shot: OneShot{ keys: one_shot, held: HashMap::new(), armed: vec![] },
+        }
+    }
+
+    /// Keys sent by chords and held tap-hold keys, they need to be declared on the device.
+    pub fn targets(&self) -> Vec<K>{
+        self.chords.chords.iter().map(|(_, output)| *output).chain(self.tap_hold.keys.values().copied()).collect()
+    }
+
+    /// Handles a key event at `time`, returns the events to emit.
+    pub fn event(&mut self, key: K, state: i32, time: f64) -> KeyEvents<K>{
+        let mut chorded = vec![];
+        self.chords.event(key, state, time, &mut chorded);
+        self.after_chords(chorded, time)
+    }
+
+    /// Handles timeouts, returns the events to emit.
+    pub fn tick(&mut self, time: f64) -> KeyEvents<K>{
+        let mut chorded = vec![];
+        self.chords.tick(time, &mut chorded);
+
+        let mut events = self.after_chords(chorded, time);
+        let mut held = vec![];
+        self.tap_hold.tick(time, &mut held);
+        for (key, state) in held {
+            self.one_shot.event(key, state, &mut events);
+        }
+        events
+    }
+
+    /// Time `tick` should be called next, `None` when nothing is waiting.
+    pub fn next_due(&self) -> Option<f64>{
+        [self.chords.next_due(), self.tap_hold.next_due()].into_iter().flatten().reduce(f64::min)
+    }
+
+    fn after_chords(&mut self, chorded: KeyEvents<K>, time: f64) -> KeyEvents<K>{
+        let mut held = vec![];
+        for (key, state) in chorded {
+            self.tap_hold.event(key, state, time, &mut held);
+        }
+
+        let mut events = vec![];
+        for (key, state) in held {
+            self.one_shot.event(key, state, &mut events);
+        }
+        events
+    }
+}
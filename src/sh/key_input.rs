@@ -1,3 +1,5 @@
+use super::config_file::ConfigFile;
+
 #[test]
 fn should_fire_only_second_time(){
     let data1 = 0b0001;
@@ -12,27 +14,281 @@ fn should_fire_only_second_time(){
     assert!(key.get_event(data4).is_some());
 }
 
+#[test]
+fn should_filter_bounces(){
+    let eager = DebounceSettings{ min_press: 0.02, min_release: 0.02, deferred: false };
+    let mut key = KeyInputU8::new(0, 'a').with_debounce(eager);
+
+    assert_eq!(key.get_event_at(1, 0.0), Some(('a', 1)));
+    assert_eq!(key.get_event_at(0, 0.003), None);
+    assert_eq!(key.get_event_at(1, 0.005), None);
+    assert_eq!(key.take_glitches(), 1);
+    // A short tap is still released, once the press lasted long enough.
+    assert_eq!(key.get_event_at(0, 0.01), None);
+    assert_eq!(key.next_due(), Some(0.02));
+    assert_eq!(key.get_event_at(0, 0.02), Some(('a', 0)));
+
+    let deferred = DebounceSettings{ deferred: true, ..eager };
+    let mut key = KeyInputU128::new(100, 'b').with_debounce(deferred);
+
+    assert_eq!(key.get_event_at(1 << 100, 1.0), None);
+    assert_eq!(key.pressed_mask(), 0);
+    assert_eq!(key.get_event_at(0, 1.003), None);
+    assert_eq!(key.take_glitches(), 1);
+    key.get_event_at(1 << 100, 1.1);
+    assert_eq!(key.get_event_at(1 << 100, 1.12), Some(('b', 1)));
+    assert_eq!(key.pressed_mask(), 1 << 100);
+}
+
+#[test]
+fn should_press_layout_keys(){
+    let deferred = DebounceSettings{ min_press: 0.02, min_release: 0.02, deferred: true };
+    let mut keys = LayoutInput::new(&['a', 'b', 'c'], deferred).with_mask(0b101);
+
+    assert_eq!(keys.get_events_at(0.0), vec![]);
+    assert_eq!(keys.next_due(), Some(0.02));
+    assert_eq!(keys.get_events_at(0.02), vec![(0, 'a', 1), (2, 'c', 1)]);
+    keys.set_mask(0b1000);
+    assert_eq!(keys.release(), vec![(0, 'a', 0), (2, 'c', 0)]);
+    assert_eq!(keys.mask(), 0b1000);
+}
+
+const SECTION_PREFIX: &str = "debounce ";
+// Filtered glitches are printed at most this often, in seconds.
+const GLITCH_REPORT_INTERVAL: f64 = 10.0;
+
+/// Debouncing of the keys of one protocol, from its `[debounce <protocol>]` section.
+///
+/// In eager mode a change is sent right away, and the key then keeps its state for at
+/// least `min_press` or `min_release`. In deferred mode a change is only sent once it
+/// lasted that long, which filters glitches without ever sending them but adds latency.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DebounceSettings {
+    /// Shortest press, in seconds.
+    pub min_press: f64,
+    /// Shortest gap between a release and the next press, in seconds.
+    pub min_release: f64,
+    pub deferred: bool,
+}
+
+impl DebounceSettings {
+    /// Reads `min_press` and `min_release` in milliseconds and `mode`, `eager` or `deferred`.
+    pub fn from_config(config: &ConfigFile, protocol: &str) -> Self {
+        let section = format!("{}{}", SECTION_PREFIX, protocol);
+
+        let deferred = match config.get(&section, "mode").unwrap_or("eager") {
+            "deferred" => true,
+            "eager" => false,
+            other => {
+                println!("Unknown debounce mode for {}: {}", protocol, other);
+                false
+            }
+        };
+
+        Self{
+            min_press: config.get_or(&section, "min_press", 0.0f64).max(0.0) / 1000.0,
+            min_release: config.get_or(&section, "min_release", 0.0f64).max(0.0) / 1000.0,
+            deferred,
+        }
+    }
+}
+
+/// Debounce state of one key, shared by the mask types.
+#[derive(Debug)]
+struct Debounce {
+    settings: DebounceSettings,
+    // The state in the latest mask and since when it is in it.
+    raw: u8,
+    raw_since: f64,
+    changed_at: f64,
+    pending: bool,
+    glitches: u32,
+}
+
+impl Debounce {
+    fn new(settings: DebounceSettings) -> Self {
+        Self{ settings, raw: 0, raw_since: f64::NEG_INFINITY, changed_at: f64::NEG_INFINITY, pending: false, glitches: 0 }
+    }
+
+    /// Whether the key should change from `current` to the `raw` state at `time`.
+    fn update(&mut self, current: u8, raw: u8, time: f64) -> bool {
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = time;
+        }
+
+        if raw == current {
+            // A change that went back before it was sent is a glitch.
+            if self.pending {
+                self.pending = false;
+                self.glitches += 1;
+            }
+            return false;
+        }
+
+        self.pending = time < self.due(current);
+        if !self.pending {
+            self.changed_at = time;
+        }
+        !self.pending
+    }
+
+    fn due(&self, current: u8) -> f64 {
+        match self.settings.deferred {
+            false => self.changed_at + self.hold(current),
+            true => self.raw_since + self.hold(self.raw),
+        }
+    }
+
+    fn hold(&self, state: u8) -> f64 {
+        match state {
+            1 => self.settings.min_press,
+            _ => self.settings.min_release,
+        }
+    }
+
+    fn next_due(&self, current: u8) -> Option<f64>{
+        (self.raw != current).then(|| self.due(current))
+    }
+}
+
+/// Prints how many glitches the debouncing of a device filtered, now and then.
+#[derive(Debug)]
+pub struct GlitchReport {
+    device: String,
+    total: u32,
+    unreported: u32,
+    reported_at: f64,
+}
+
+impl GlitchReport {
+    pub fn new(device: &str) -> Self {
+        Self{ device: device.to_string(), total: 0, unreported: 0, reported_at: f64::NEG_INFINITY }
+    }
+
+    pub fn add(&mut self, glitches: u32, time: f64){
+        self.total += glitches;
+        self.unreported += glitches;
+
+        if self.unreported > 0 && time - self.reported_at >= GLITCH_REPORT_INTERVAL {
+            println!("Filtered {} key glitches on {}, {} in total.", self.unreported, self.device, self.total);
+            self.unreported = 0;
+            self.reported_at = time;
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct KeyInputU128<T> {
     mask: u128,
     key: T,
     current_state: u8,
+    debounce: Debounce,
 }
 
 impl<T: Clone + Copy> KeyInputU128<T> {
     pub fn new(bit: u8, key: T) -> Self{
-        Self{ mask: 0u128 | (1 << bit), key, current_state: 0 }
+        Self{ mask: 1 << bit, key, current_state: 0, debounce: Debounce::new(DebounceSettings::default()) }
+    }
+
+    pub fn with_debounce(mut self, settings: DebounceSettings) -> Self {
+        self.debounce = Debounce::new(settings);
+        self
     }
 
     pub fn get_event(&mut self, mask: u128) -> Option<(T, i32)>{
+        self.get_event_at(mask, 0.0)
+    }
+
+    /// Like `get_event`, for keys with debouncing. Time is in seconds, and the latest mask
+    /// has to be passed again at `next_due` for changes that were held back.
+    pub fn get_event_at(&mut self, mask: u128, time: f64) -> Option<(T, i32)>{
         let state = if self.mask & mask > 0 {1} else {0};
 
-        if self.current_state == state {
+        if !self.debounce.update(self.current_state, state, time) {
             return None;
         }
 
         self.current_state = state;
-        return Some((self.key, self.current_state as i32));
+        Some((self.key, self.current_state as i32))
+    }
+
+    /// Time a held back change is due, `None` when there is none.
+    pub fn next_due(&self) -> Option<f64>{
+        self.debounce.next_due(self.current_state)
+    }
+
+    /// The bit of the key while it is pressed, so debounced keys can be merged into a mask.
+    pub fn pressed_mask(&self) -> u128 {
+        match self.current_state {
+            0 => 0,
+            _ => self.mask,
+        }
+    }
+
+    /// Glitches filtered since the last call.
+    pub fn take_glitches(&mut self) -> u32 {
+        std::mem::take(&mut self.debounce.glitches)
+    }
+
+    /// Releases the key right away, without debouncing, e.g. when its client is gone.
+    pub fn release(&mut self) -> Option<(T, i32)>{
+        self.debounce = Debounce::new(self.debounce.settings);
+        match std::mem::take(&mut self.current_state) {
+            0 => None,
+            _ => Some((self.key, 0)),
+        }
+    }
+}
+
+/// The keys of a layout, from bit 0 up, and the latest mask a client sent for them.
+#[derive(Debug)]
+pub struct LayoutInput<T> {
+    keys: Vec<KeyInputU128<T>>,
+    // Passed again for debounced changes that were held back.
+    mask: u128,
+}
+
+impl<T: Clone + Copy> LayoutInput<T> {
+    pub fn new(layout: &[T], debounce: DebounceSettings) -> Self {
+        let keys = layout.iter().enumerate().map(|(bit, key)| KeyInputU128::new(bit as u8, *key).with_debounce(debounce)).collect();
+        Self{ keys, mask: 0 }
+    }
+
+    pub fn with_mask(mut self, mask: u128) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn set_mask(&mut self, mask: u128){
+        self.mask = mask;
+    }
+
+    pub fn mask(&self) -> u128 {
+        self.mask
+    }
+
+    /// Key events for the latest mask at `time`, with the index of the key in the layout.
+    pub fn get_events_at(&mut self, time: f64) -> Vec<(usize, T, i32)>{
+        let mask = self.mask;
+        self.keys.iter_mut().enumerate()
+            .filter_map(|(index, key)| key.get_event_at(mask, time).map(|(key, state)| (index, key, state)))
+            .collect()
+    }
+
+    pub fn next_due(&self) -> Option<f64>{
+        self.keys.iter().filter_map(|key| key.next_due()).reduce(f64::min)
+    }
+
+    pub fn take_glitches(&mut self) -> u32 {
+        self.keys.iter_mut().map(|key| key.take_glitches()).sum()
+    }
+
+    /// Releases all keys without debouncing, the mask is kept.
+    pub fn release(&mut self) -> Vec<(usize, T, i32)>{
+        self.keys.iter_mut().enumerate()
+            .filter_map(|(index, key)| key.release().map(|(key, state)| (index, key, state)))
+            .collect()
     }
 }
 
@@ -41,26 +297,58 @@ pub struct KeyInputU8<T> {
     mask: u8,
     key: T,
     current_state: u8,
+    debounce: Debounce,
 }
 
 impl<T: Clone + Copy> KeyInputU8<T> {
     pub fn new(bit: u8, key: T) -> Self{
-        Self{ mask: 0u8 | (1 << bit), key, current_state: 0 }
+        Self{ mask: 1 << bit, key, current_state: 0, debounce: Debounce::new(DebounceSettings::default()) }
+    }
+
+    pub fn with_debounce(mut self, settings: DebounceSettings) -> Self {
+        self.debounce = Debounce::new(settings);
+        self
+    }
+
+    /// Whether `get_event` would report a change for this mask, without consuming it.
+    pub fn would_change(&self, mask: u8) -> bool{
+        let state = if self.mask & mask > 0 {1} else {0};
+        self.current_state != state
     }
 
     pub fn get_event(&mut self, mask: u8) -> Option<(T, i32)>{
+        self.get_event_at(mask, 0.0)
+    }
+
+    /// Like `get_event`, for keys with debouncing. Time is in seconds, and the latest mask
+    /// has to be passed again at `next_due` for changes that were held back.
+    pub fn get_event_at(&mut self, mask: u8, time: f64) -> Option<(T, i32)>{
         let state = if self.mask & mask > 0 {1} else {0};
 
-        if self.current_state == state {
+        if !self.debounce.update(self.current_state, state, time) {
             return None;
         }
 
         self.current_state = state;
-        return Some((self.key, self.current_state as i32));
+        Some((self.key, self.current_state as i32))
     }
-}
-
-
 
+    /// Time a held back change is due, `None` when there is none.
+    pub fn next_due(&self) -> Option<f64>{
+        self.debounce.next_due(self.current_state)
+    }
 
+    /// Glitches filtered since the last call.
+    pub fn take_glitches(&mut self) -> u32 {
+        std::mem::take(&mut self.debounce.glitches)
+    }
 
+    /// Releases the key right away, without debouncing, e.g. when the settings change.
+    pub fn release(&mut self) -> Option<(T, i32)>{
+        self.debounce = Debounce::new(self.debounce.settings);
+        match std::mem::take(&mut self.current_state) {
+            0 => None,
+            _ => Some((self.key, 0)),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+#[test]
+fn should_find_key_bits(){
+    assert_eq!(key_bit("A"), Some(10));
+    assert_eq!(key_bit("f12"), Some(47));
+    assert_eq!(key_bit("nope"), None);
+    // The device names of the keys work too.
+    assert_eq!(key_bit("leftctrl"), Some(55));
+    assert_eq!(key_bit("KEY_A"), Some(10));
+    assert_eq!(device_key_name("ctrl"), "leftctrl");
+    assert_eq!(device_key_name("KEY_Space"), "space");
+
+    assert_eq!(parse_chord("ctrl+shift+t"), Some(1 << 55 | 1 << 56 | 1 << 29));
+    assert_eq!(parse_chord("ctrl+nope"), None);
+    assert_eq!(parse_chord("alt+meta+ctrl+shift+rightalt+rightctrl+rightshift"), Some(MODIFIERS));
+
+    assert_eq!(char_key('q'), Some((26, false)));
+    assert_eq!(char_key('Q'), Some((26, true)));
+    assert_eq!(char_key('?'), Some((76, true)));
+    assert_eq!(char_key('é'), None);
+}
+
+// Names of the keys in the order of their bits in the `KEYBOARD` key mask.
+const KEY_NAMES: [&str; 86] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m",
+    "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+    "f1", "f2", "f3", "f4", "f5", "f6", "f7", "f8", "f9", "f10", "f11", "f12",
+    "esc", "grave", "space", "enter", "title",
+    "alt", "meta", "ctrl", "shift", "backspace", "tab", "capslock",
+    "up", "down", "left", "right",
+    "rightalt", "rightctrl", "rightshift",
+    "minus", "equal", "leftbrace", "rightbrace", "semicolon", "apostrophe", "backslash", "comma", "dot", "slash",
+    "insert", "delete", "home", "end", "pageup", "pagedown", "print", "scrolllock", "pause",
+];
+
+/// The modifier keys in the `KEYBOARD` key mask. In a chord they are pressed before
+/// and released after the other keys, or the other keys would go out unmodified.
+pub const MODIFIERS: u128 = 0b1111 << 53 | 0b111 << 64;
+
+// The mask names the left modifiers without their side, the devices with it.
+const MODIFIER_NAMES: [(&str, &str); 4] = [("alt", "leftalt"), ("meta", "leftmeta"), ("ctrl", "leftctrl"), ("shift", "leftshift")];
+
+/// Returns the bit of a key in the `KEYBOARD` key mask, e.g. `a`, `f5`, `ctrl` or `pageup`.
+/// Device names like `leftctrl` or `KEY_A` are accepted as well.
+pub fn key_bit(name: &str) -> Option<u32>{
+    let name = lowercase_name(name);
+    let name = MODIFIER_NAMES.iter()
+        .find(|(_, device_name)| *device_name == name)
+        .map(|(mask_name, _)| mask_name.to_string())
+        .unwrap_or(name);
+    KEY_NAMES.iter().position(|key| *key == name).map(|bit| bit as u32)
+}
+
+/// The device name of a key without the `KEY_` prefix, in lower case. Takes the names of
+/// the `KEYBOARD` key mask too, so `ctrl` gives `leftctrl`.
+pub fn device_key_name(name: &str) -> String {
+    let name = lowercase_name(name);
+    MODIFIER_NAMES.iter()
+        .find(|(mask_name, _)| *mask_name == name)
+        .map(|(_, device_name)| device_name.to_string())
+        .unwrap_or(name)
+}
+
+fn lowercase_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    match name.strip_prefix("key_") {
+        Some(name) => name.to_string(),
+        None => name,
+    }
+}
+
+/// Parses keys joined with `+`, like `ctrl+shift+t`, into a `KEYBOARD` key mask.
+pub fn parse_chord(chord: &str) -> Option<u128>{
+    chord.split('+').try_fold(0u128, |mask, name| Some(mask | 1 << key_bit(name)?))
+}
+
+/// Returns the key bit that types `character` on a US layout, and whether it needs shift.
+pub fn char_key(character: char) -> Option<(u32, bool)>{
+    const SHIFTED: [(char, char); 21] = [
+        ('!', '1'), ('@', '2'), ('#', '3'), ('$', '4'), ('%', '5'), ('^', '6'), ('&', '7'),
+        ('*', '8'), ('(', '9'), (')', '0'), ('_', '-'), ('+', '='), ('{', '['), ('}', ']'),
+        (':', ';'), ('"', '\''), ('|', '\\'), ('<', ','), ('>', '.'), ('?', '/'), ('~', '`'),
+    ];
+
+    let (unshifted, shift) = match SHIFTED.iter().find(|(shifted, _)| *shifted == character) {
+        Some((_, unshifted)) => (*unshifted, true),
+        None if character.is_ascii_uppercase() => (character.to_ascii_lowercase(), true),
+        None => (character, false),
+    };
+
+    let name = match unshifted {
+        ' ' => "space",
+        '\n' => "enter",
+        '\t' => "tab",
+        '-' => "minus",
+        '=' => "equal",
+        '[' => "leftbrace",
+        ']' => "rightbrace",
+        ';' => "semicolon",
+        '\'' => "apostrophe",
+        '\\' => "backslash",
+        ',' => "comma",
+        '.' => "dot",
+        '/' => "slash",
+        '`' => "grave",
+        character if character.is_ascii_alphanumeric() => return key_bit(&character.to_string()).map(|bit| (bit, shift)),
+        _ => return None,
+    };
+
+    key_bit(name).map(|bit| (bit, shift))
+}
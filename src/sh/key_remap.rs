@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_remap_keys(){
+    let config = ConfigFile::parse("[remap]\ncapslock = leftctrl\nleftctrl = capslock\ninsert = none\nf1 = nope\n");
+    let mut remap = KeyRemap::from_config(&config, parse_name);
+
+    assert_eq!(remap.remap("capslock", 1), Some(("leftctrl", 1)));
+    assert_eq!(remap.remap("leftctrl", 1), Some(("capslock", 1)));
+    assert_eq!(remap.remap("capslock", 0), Some(("leftctrl", 0)));
+    assert_eq!(remap.remap("insert", 1), None);
+    assert_eq!(remap.remap("f1", 1), Some(("f1", 1)));
+    assert_eq!(remap.targets(), vec!["capslock", "leftctrl"]);
+}
+
+#[test]
+fn should_switch_layers(){
+    let config = ConfigFile::parse("[layer nav]\nhold = rightalt\nh = left\n[layer numbers]\ntoggle = scrolllock\nh = 4\n");
+    let mut remap = KeyRemap::from_config(&config, parse_name);
+
+    assert_eq!(remap.remap("rightalt", 1), None);
+    assert_eq!(remap.remap("h", 1), Some(("left", 1)));
+    // The key is released as what it was pressed as, even after the layer is gone.
+    assert_eq!(remap.remap("rightalt", 0), None);
+    assert_eq!(remap.remap("h", 0), Some(("left", 0)));
+    assert_eq!(remap.remap("h", 1), Some(("h", 1)));
+    assert_eq!(remap.remap("h", 0), Some(("h", 0)));
+
+    remap.remap("scrolllock", 1);
+    remap.remap("scrolllock", 0);
+    assert_eq!(remap.remap("h", 1), Some(("4", 1)));
+}
+
+#[cfg(test)]
+fn parse_name(name: &str) -> Option<&'static str> {
+    ["capslock", "leftctrl", "insert", "f1", "rightalt", "scrolllock", "h", "left", "4"].into_iter().find(|key| *key == name)
+}
+
+const SECTION: &str = "remap";
+const LAYER_PREFIX: &str = "layer ";
+
+/// A layer of remaps, switched on while its `hold` key is held or by tapping its `toggle` key.
+#[derive(Debug)]
+struct Layer<K> {
+    hold: Option<K>,
+    toggle: Option<K>,
+    remaps: HashMap<K, Option<K>>,
+    held: bool,
+    toggled: bool,
+}
+
+impl<K> Layer<K> {
+    fn active(&self) -> bool {
+        self.held || self.toggled
+    }
+}
+
+/// Remaps the decoded key events of a device before they are emitted.
+///
+/// The `[remap]` section maps a key to another one, or to `none` to disable it. Every
+/// `[layer <name>]` section can add more remaps on top, the layer defined last wins when
+/// several are active. Layer keys are not passed on to the device.
+///
+/// Keys are named as in the config and turned into device keys by `parse`, so the same
+/// sections apply to every device.
+#[derive(Debug)]
+pub struct KeyRemap<K> {
+    remaps: HashMap<K, Option<K>>,
+    layers: Vec<Layer<K>>,
+    // What every held key was pressed as, `None` when nothing was pressed.
+    pressed: HashMap<K, Option<K>>,
+    // How many held keys press the same output key.
+    held: HashMap<K, u32>,
+}
+
+impl<K: Copy + Eq + Hash + Ord> KeyRemap<K> {
+    pub fn from_config(config: &ConfigFile, parse: impl Fn(&str) -> Option<K>) -> Self {
+        let remaps = parse_remaps(config, SECTION, &parse);
+
+        let mut layers = vec![];
+        for section in config.sections() {
+            if !section.starts_with(LAYER_PREFIX) {
+                continue;
+            }
+
+            let key = |name: &str| {
+                let key = config.get(section, name).and_then(&parse);
+                if config.get(section, name).is_some() && key.is_none() {
+                    println!("Unknown {} key of [{}]", name, section);
+                }
+                key
+            };
+
+            let (hold, toggle) = (key("hold"), key("toggle"));
+            if hold.is_none() && toggle.is_none() {
+                println!("[{}] has neither a hold nor a toggle key and is never used.", section);
+                continue;
+            }
+
+            layers.push(Layer{ hold, toggle, remaps: parse_remaps(config, section, &parse), held: false, toggled: false });
+        }
+
+        Self{ remaps, layers, pressed: HashMap::new(), held: HashMap::new() }
+    }
+
+    /// All keys something can be remapped to, they need to be declared on the device.
+    pub fn targets(&self) -> Vec<K>{
+        let mut targets: Vec<K> = self.remaps.values()
+            .chain(self.layers.iter().flat_map(|layer| layer.remaps.values()))
+            .flatten()
+            .copied()
+            .collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    /// Remaps a key event, `None` when nothing should be emitted.
+    pub fn remap(&mut self, key: K, state: i32) -> Option<(K, i32)>{
+        match state {
+            0 => self.release(key),
+            1 => self.press(key),
+            // Repeats follow the key the press went to.
+            _ => self.pressed.get(&key).copied().flatten().map(|output| (output, state)),
+        }
+    }
+
+    fn press(&mut self, key: K) -> Option<(K, i32)>{
+        if self.pressed.contains_key(&key) {
+            return None;
+        }
+
+        if let Some(layer) = self.layers.iter_mut().find(|layer| layer.hold == Some(key) || layer.toggle == Some(key)) {
+            match layer.hold == Some(key) {
+                true => layer.held = true,
+                false => layer.toggled = !layer.toggled,
+            }
+            self.pressed.insert(key, None);
+            return None;
+        }
+
+        let output = self.layers.iter().rev()
+            .filter(|layer| layer.active())
+            .find_map(|layer| layer.remaps.get(&key))
+            .or_else(|| self.remaps.get(&key))
+            .copied()
+            .unwrap_or(Some(key));
+        self.pressed.insert(key, output);
+
+        let output = output?;
+        let count = self.held.entry(output).or_insert(0);
+        *count += 1;
+        match *count {
+            1 => Some((output, 1)),
+            _ => None,
+        }
+    }
+
+    fn release(&mut self, key: K) -> Option<(K, i32)>{
+        for layer in self.layers.iter_mut().filter(|layer| layer.hold == Some(key)) {
+            layer.held = false;
+        }
+
+        let output = self.pressed.remove(&key)??;
+        let count = self.held.get_mut(&output)?;
+        *count -= 1;
+        match *count {
+            0 => {
+                self.held.remove(&output);
+                Some((output, 0))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn parse_remaps<K: Eq + Hash>(config: &ConfigFile, section: &str, parse: &impl Fn(&str) -> Option<K>) -> HashMap<K, Option<K>>{
+    let mut remaps = HashMap::new();
+
+    for (from, to) in config.entries(section) {
+        if section != SECTION && (from == "hold" || from == "toggle") {
+            continue;
+        }
+
+        let from_key = match parse(from) {
+            Some(key) => key,
+            None => {
+                println!("Unknown key in [{}]: {}", section, from);
+                continue;
+            }
+        };
+
+        let to_key = match to.trim() {
+            "none" => None,
+            to => match parse(to) {
+                Some(key) => Some(key),
+                None => {
+                    println!("Unknown key in [{}]: {}", section, to);
+                    continue;
+                }
+            },
+        };
+
+        remaps.insert(from_key, to_key);
+    }
+
+    remaps
+}
@@ -0,0 +1,15 @@
+use super::clients::ClientId;
+
+/// Messages handled by the keyboard thread.
+pub enum KeyboardMessage {
+    /// Data of the `KEYBOARD` protocol, a mask of the pressed keys.
+    Keys(String),
+    /// Data of the `STICK` protocol, see `StickMapper`.
+    Stick(String),
+    /// Keys from a server side mapper that are pressed together and released again.
+    Chord(u128),
+    /// All keys currently held by running macros.
+    Macro(u128),
+    /// A client connected, it is sent the current LED state.
+    Connected(ClientId),
+}
@@ -0,0 +1,21 @@
+#[test]
+fn should_format_led_message(){
+    let leds = LedState{ caps_lock: true, ..LedState::default() };
+
+    assert_eq!(leds.to_message(), "LED|caps=1;num=0;scroll=0");
+}
+
+/// Lock LEDs of the virtual keyboard, as set by the system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LedState {
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl LedState {
+    /// Formats the state as sent to the clients: `LED|caps=1;num=0;scroll=0`
+    pub fn to_message(&self) -> String {
+        format!("LED|caps={};num={};scroll={}", self.caps_lock as u8, self.num_lock as u8, self.scroll_lock as u8)
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::config_file::SharedConfig;
+use super::config_watcher::ReloadTracker;
+use super::keyboard_data::KeyboardMessage;
+use super::macros::{load_macros, MacroEngine, MacroOutput};
+use super::mouse_data::MouseMessage;
+
+// How long to wait for a message while no macro is running.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runs the macros started with `MACRO|name` on this thread, so waiting between steps
+/// never blocks a device thread. `MACRO|cancel` stops all macros and `MACRO|cancel name`
+/// a single one, releasing the keys they held.
+pub fn start_macro_input(receiver: mpsc::Receiver<String>, keyboard_sender: mpsc::Sender<KeyboardMessage>, mouse_sender: mpsc::Sender<MouseMessage>, config: SharedConfig){
+
+    let mut engine = MacroEngine::new(load_macros(&config.lock().unwrap()));
+    let mut reloads = ReloadTracker::new(&config);
+    let started = Instant::now();
+
+    loop{
+        let timeout = match engine.next_due() {
+            Some(due) => Duration::from_secs_f64((due - started.elapsed().as_secs_f64()).max(0.0)),
+            None => IDLE_TIMEOUT,
+        };
+
+        let mut outputs = vec![];
+
+        match receiver.recv_timeout(timeout) {
+            Ok(message) => {
+                let message = message.trim();
+                match message.strip_prefix("cancel") {
+                    Some(name) if name.is_empty() || name.starts_with(' ') => {
+                        let name = name.trim();
+                        outputs = engine.cancel(if name.is_empty() { None } else { Some(name) });
+                    }
+                    _ => {
+                        if !engine.start(message, started.elapsed().as_secs_f64()) {
+                            println!("Unknown macro: {}", message);
+                        }
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Running macros are stopped, their steps may have changed.
+        if reloads.reloaded(&config) {
+            outputs.extend(engine.cancel(None));
+            engine = MacroEngine::new(load_macros(&config.lock().unwrap()));
+        }
+
+        outputs.extend(engine.advance(started.elapsed().as_secs_f64()));
+
+        for output in outputs {
+            match output {
+                MacroOutput::Keys(keys) => {
+                    let _ = keyboard_sender.send(KeyboardMessage::Macro(keys));
+                }
+                MacroOutput::Motion(x, y) => {
+                    let _ = mouse_sender.send(MouseMessage::Motion(x, y));
+                }
+                MacroOutput::Mouse{ wheel, buttons } => {
+                    let _ = mouse_sender.send(MouseMessage::Action{ modifiers: 0, wheel, buttons });
+                }
+            }
+        }
+    }
+}
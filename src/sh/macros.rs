@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use super::config_file::ConfigFile;
+use super::key_names::{char_key, key_bit, parse_chord, MODIFIERS};
+
+#[test]
+fn should_load_macros_from_config(){
+    let config = ConfigFile::parse("[macro copy]\nstep = tap ctrl+c\nstep = delay 50\nstep = text Hi\n[mouse]\nsensitivity = 2\n[macro bad]\nstep = fly away\n");
+    let macros = load_macros(&config);
+
+    let shift = 1 << 56;
+    let h = 1 << key_bit("h").unwrap();
+    let i = 1 << key_bit("i").unwrap();
+    assert_eq!(macros["copy"][..6], [MacroStep::Down(1 << 55), MacroStep::Down(1 << 12), MacroStep::Up(1 << 12), MacroStep::Up(1 << 55), MacroStep::Delay(0.05), MacroStep::Down(shift)]);
+    assert_eq!(macros["copy"][6..9], [MacroStep::Down(h), MacroStep::Up(h), MacroStep::Up(shift)]);
+    assert!(macros["copy"].contains(&MacroStep::Down(i)));
+    assert!(!macros.contains_key("bad"));
+}
+
+#[test]
+fn should_run_macro_steps_in_time(){
+    let config = ConfigFile::parse("[macro drag]\nstep = down shift\nstep = click left\nstep = delay 100\nstep = move 5 -5\nstep = up shift\n");
+    let mut engine = MacroEngine::new(load_macros(&config));
+    let shift = 1 << 56;
+
+    assert!(engine.start("drag", 1.0));
+    assert!(!engine.start("missing", 1.0));
+    assert_eq!(engine.advance(1.0), vec![MacroOutput::Keys(shift), MacroOutput::Mouse{ wheel: 0, buttons: 1 }]);
+    assert_eq!(engine.next_due(), Some(1.1));
+    assert!(engine.advance(1.05).is_empty());
+    assert_eq!(engine.advance(1.1), vec![MacroOutput::Motion(5.0, -5.0), MacroOutput::Keys(0)]);
+    assert_eq!(engine.next_due(), None);
+}
+
+#[test]
+fn should_press_modifiers_first_and_release_them_last(){
+    let config = ConfigFile::parse("[macro copy]\nstep = tap ctrl+c\n");
+    let mut engine = MacroEngine::new(load_macros(&config));
+    let (ctrl, c) = (1 << 55, 1 << 12);
+
+    engine.start("copy", 0.0);
+    assert_eq!(engine.advance(0.0), vec![MacroOutput::Keys(ctrl), MacroOutput::Keys(ctrl | c), MacroOutput::Keys(ctrl), MacroOutput::Keys(0)]);
+}
+
+#[test]
+fn should_release_keys_of_cancelled_macro(){
+    let config = ConfigFile::parse("[macro hold]\nstep = down ctrl\nstep = delay 1000\nstep = up ctrl\n");
+    let mut engine = MacroEngine::new(load_macros(&config));
+
+    engine.start("hold", 0.0);
+    assert_eq!(engine.advance(0.0), vec![MacroOutput::Keys(1 << 55)]);
+    assert_eq!(engine.cancel(Some("hold")), vec![MacroOutput::Keys(0)]);
+    assert!(engine.advance(2.0).is_empty());
+}
+
+const SECTION_PREFIX: &str = "macro ";
+// Pause after every typed character, some applications drop keys typed faster.
+const TYPING_DELAY: f64 = 0.01;
+
+/// A single step of a macro, `text` and `tap` steps are expanded into key downs and ups.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroStep {
+    /// Presses the keys of a `KEYBOARD` key mask.
+    Down(u128),
+    /// Releases the keys of a `KEYBOARD` key mask.
+    Up(u128),
+    /// Waits, in seconds.
+    Delay(f64),
+    Move(f32, f32),
+    /// Clicks the mouse buttons of the mask, bit 0 is the left button.
+    Click(u8),
+    Wheel(i32),
+}
+
+impl MacroStep {
+    /// Parses one `step` line:
+    /// `down <keys>`, `up <keys>`, `tap <keys>`, `text <text>`, `delay <ms>`,
+    /// `move <x> <y>`, `click <left|right|middle>` or `wheel <steps>`.
+    /// Keys are joined with `+`, like `ctrl+shift+t`.
+    pub fn parse(step: &str) -> Option<Vec<MacroStep>>{
+        let (command, argument) = step.trim().split_once(' ').unwrap_or((step.trim(), ""));
+        let argument = argument.trim();
+
+        let steps = match command {
+            "down" => press(parse_chord(argument)?),
+            "up" => release(parse_chord(argument)?),
+            "tap" => {
+                let keys = parse_chord(argument)?;
+                [press(keys), release(keys)].concat()
+            }
+            "text" => {
+                let shift = 1 << key_bit("shift")?;
+                let mut steps = vec![];
+                for character in argument.chars() {
+                    let (bit, shifted) = char_key(character)?;
+                    let key = 1 << bit;
+                    match shifted {
+                        true => steps.extend([MacroStep::Down(shift), MacroStep::Down(key), MacroStep::Up(key), MacroStep::Up(shift)]),
+                        false => steps.extend([MacroStep::Down(key), MacroStep::Up(key)]),
+                    }
+                    steps.push(MacroStep::Delay(TYPING_DELAY));
+                }
+                steps
+            }
+            "delay" => vec![MacroStep::Delay(argument.parse::<f64>().ok()?.max(0.0) / 1000.0)],
+            "move" => {
+                let (x, y) = argument.split_once(' ')?;
+                vec![MacroStep::Move(x.trim().parse::<f32>().ok()?, y.trim().parse::<f32>().ok()?)]
+            }
+            "click" => vec![MacroStep::Click(match argument {
+                "left" => 0b001,
+                "right" => 0b010,
+                "middle" => 0b100,
+                _ => return None,
+            })],
+            "wheel" => vec![MacroStep::Wheel(argument.parse::<i32>().ok()?)],
+            _ => return None,
+        };
+
+        Some(steps)
+    }
+}
+
+/// Presses the modifiers of a chord in a step of their own, before the other keys.
+fn press(keys: u128) -> Vec<MacroStep>{
+    [keys & MODIFIERS, keys & !MODIFIERS].into_iter()
+        .filter(|keys| *keys != 0)
+        .map(MacroStep::Down)
+        .collect()
+}
+
+/// Releases the other keys of a chord before its modifiers.
+fn release(keys: u128) -> Vec<MacroStep>{
+    [keys & !MODIFIERS, keys & MODIFIERS].into_iter()
+        .filter(|keys| *keys != 0)
+        .map(MacroStep::Up)
+        .collect()
+}
+
+/// Reads the macros from `[macro <name>]` sections, each `step = ...` line is one step.
+/// A macro with a malformed step is skipped as a whole.
+pub fn load_macros(config: &ConfigFile) -> HashMap<String, Vec<MacroStep>>{
+    let mut macros = HashMap::new();
+
+    for section in config.sections() {
+        let name = match section.strip_prefix(SECTION_PREFIX) {
+            Some(name) => name.trim(),
+            None => continue,
+        };
+
+        let mut steps = vec![];
+        for (key, value) in config.entries(section) {
+            if key != "step" {
+                continue;
+            }
+            match MacroStep::parse(value) {
+                Some(parsed) => steps.extend(parsed),
+                None => {
+                    println!("Invalid step in macro {}: {}", name, value);
+                    steps.clear();
+                    break;
+                }
+            }
+        }
+
+        if !steps.is_empty() {
+            macros.insert(name.to_string(), steps);
+        }
+    }
+
+    macros
+}
+
+/// What a running macro does to the virtual devices.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroOutput {
+    /// All keys currently held by macros, as a `KEYBOARD` key mask.
+    Keys(u128),
+    Motion(f32, f32),
+    Mouse{ wheel: i32, buttons: u8 },
+}
+
+#[derive(Debug)]
+struct MacroRun {
+    name: String,
+    position: usize,
+    next_at: f64,
+    held: u128,
+}
+
+/// Runs the configured macros, several can run at the same time.
+///
+/// Time is passed in seconds. Keys pressed by a macro are tracked, so cancelling it
+/// releases them.
+#[derive(Debug)]
+pub struct MacroEngine {
+    macros: HashMap<String, Vec<MacroStep>>,
+    running: Vec<MacroRun>,
+}
+
+impl MacroEngine {
+    pub fn new(macros: HashMap<String, Vec<MacroStep>>) -> Self {
+        Self{ macros, running: vec![] }
+    }
+
+    /// Starts the macro, returns false when there is no such macro. A macro that is
+    /// already running is not started twice.
+    pub fn start(&mut self, name: &str, time: f64) -> bool {
+        if !self.macros.contains_key(name) {
+            return false;
+        }
+
+        if !self.running.iter().any(|run| run.name == name) {
+            self.running.push(MacroRun{ name: name.to_string(), position: 0, next_at: time, held: 0 });
+        }
+        true
+    }
+
+    /// Stops the named macro, or all of them, and releases the keys they held.
+    pub fn cancel(&mut self, name: Option<&str>) -> Vec<MacroOutput>{
+        let held_before = self.held();
+        self.running.retain(|run| name.is_some() && Some(run.name.as_str()) != name);
+
+        match self.held() != held_before {
+            true => vec![MacroOutput::Keys(self.held())],
+            false => vec![],
+        }
+    }
+
+    /// Time the next step is due, `None` when no macro is running.
+    pub fn next_due(&self) -> Option<f64>{
+        self.running.iter().map(|run| run.next_at).reduce(f64::min)
+    }
+
+    /// Runs all steps that are due at `time`.
+    pub fn advance(&mut self, time: f64) -> Vec<MacroOutput>{
+        let mut outputs = vec![];
+
+        for index in 0..self.running.len() {
+            while self.running[index].next_at <= time {
+                let run = &mut self.running[index];
+                let step = match self.macros[&run.name].get(run.position) {
+                    Some(step) => step.clone(),
+                    None => break,
+                };
+                run.position += 1;
+
+                match step {
+                    MacroStep::Down(keys) => run.held |= keys,
+                    MacroStep::Up(keys) => run.held &= !keys,
+                    MacroStep::Delay(delay) => run.next_at += delay,
+                    MacroStep::Move(x, y) => outputs.push(MacroOutput::Motion(x, y)),
+                    MacroStep::Click(buttons) => outputs.push(MacroOutput::Mouse{ wheel: 0, buttons }),
+                    MacroStep::Wheel(wheel) => outputs.push(MacroOutput::Mouse{ wheel, buttons: 0 }),
+                }
+
+                if matches!(step, MacroStep::Down(_) | MacroStep::Up(_)) {
+                    outputs.push(MacroOutput::Keys(self.held()));
+                }
+            }
+        }
+
+        // Keys a finished macro left pressed are released with it.
+        let held_before = self.held();
+        self.running.retain(|run| run.position < self.macros[&run.name].len());
+        if self.held() != held_before {
+            outputs.push(MacroOutput::Keys(self.held()));
+        }
+        outputs
+    }
+
+    fn held(&self) -> u128 {
+        self.running.iter().fold(0, |held, run| held | run.held)
+    }
+}
@@ -16,4 +16,17 @@ impl MouseData {
 
         Self{ delta_x: dx, delta_y: dy, delta_wheel: dw, button_state: buttons }
     }
-}
\ No newline at end of file
+}
+
+/// Messages handled by the mouse thread.
+pub enum MouseMessage {
+    /// Data of the `MOUSE` protocol.
+    Data(String),
+    /// Data of the `MOUSE_SETTINGS` protocol, see `MouseSettings::apply`.
+    Settings(String),
+    /// Pointer motion in pixels from a server side mapper, y grows downwards.
+    Motion(f32, f32),
+    /// Wheel steps and clicks of the buttons in `buttons` from a server side mapper,
+    /// with the modifier keys in the `KEYBOARD` key mask `modifiers` held around them.
+    Action{ modifiers: u128, wheel: i32, buttons: u8 },
+}
@@ -0,0 +1,110 @@
+use std::io;
+
+use super::config_file::{parse_bool, ConfigFile, SharedConfig};
+
+#[test]
+fn should_apply_only_the_given_settings(){
+    let mut settings = MouseSettings::default();
+    settings.apply("sensitivity=4;natural_scrolling=1;swap_buttons=true;bogus=3");
+
+    assert_eq!(settings.sensitivity, 4.0);
+    assert_eq!(settings.scroll_speed, 10.0);
+    assert!(settings.natural_scrolling);
+    assert!(settings.swap_buttons);
+    assert_eq!(settings.map_buttons(0b1001), 0b1010);
+}
+
+const SECTION: &str = "mouse";
+
+/// User adjustable mouse behaviour, kept in the `[mouse]` section of the config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MouseSettings {
+    pub sensitivity: f32,
+    pub scroll_speed: f32,
+    pub natural_scrolling: bool,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    pub swap_buttons: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self{
+            sensitivity: 8.0,
+            scroll_speed: 10.0,
+            natural_scrolling: false,
+            invert_x: false,
+            invert_y: false,
+            swap_buttons: false,
+        }
+    }
+}
+
+impl MouseSettings {
+    pub fn from_config(config: &ConfigFile) -> Self {
+        let mut settings = Self::default();
+        for (key, value) in config.entries(SECTION) {
+            settings.set(key, value);
+        }
+        settings
+    }
+
+    /// Applies a settings message in the form of `key=value;key=value`.
+    pub fn apply(&mut self, data: &str){
+        for pair in data.split(';') {
+            if let Some((key, value)) = pair.split_once('=') {
+                self.set(key.trim(), value.trim());
+            }
+        }
+    }
+
+    pub fn store(&self, config: &mut ConfigFile){
+        config.set(SECTION, "sensitivity", &self.sensitivity.to_string());
+        config.set(SECTION, "scroll_speed", &self.scroll_speed.to_string());
+        config.set(SECTION, "natural_scrolling", &(self.natural_scrolling as u8).to_string());
+        config.set(SECTION, "invert_x", &(self.invert_x as u8).to_string());
+        config.set(SECTION, "invert_y", &(self.invert_y as u8).to_string());
+        config.set(SECTION, "swap_buttons", &(self.swap_buttons as u8).to_string());
+    }
+
+    /// Stores the settings in the shared config and writes it to disk.
+    pub fn save(&self, config: &SharedConfig) -> io::Result<()>{
+        let mut config = config.lock().unwrap();
+        self.store(&mut config);
+        config.save()
+    }
+
+    pub fn motion(&self, delta_x: f32, delta_y: f32) -> (f32, f32){
+        let x = if self.invert_x { -delta_x } else { delta_x };
+        let y = if self.invert_y { -delta_y } else { delta_y };
+        (x * self.sensitivity, y * self.sensitivity)
+    }
+
+    pub fn wheel(&self, delta_wheel: f32) -> f32 {
+        let wheel = delta_wheel * self.scroll_speed;
+        if self.natural_scrolling { -wheel } else { wheel }
+    }
+
+    /// Swaps the left and right button bits when requested.
+    pub fn map_buttons(&self, button_state: u8) -> u8 {
+        if !self.swap_buttons {
+            return button_state;
+        }
+
+        let left = button_state & 0b01;
+        let right = (button_state & 0b10) >> 1;
+        (button_state & !0b11) | (left << 1) | right
+    }
+
+    fn set(&mut self, key: &str, value: &str){
+        match key {
+            "sensitivity" => if let Ok(value) = value.parse() { self.sensitivity = value },
+            "scroll_speed" => if let Ok(value) = value.parse() { self.scroll_speed = value },
+            "natural_scrolling" => if let Some(value) = parse_bool(value) { self.natural_scrolling = value },
+            "invert_x" => if let Some(value) = parse_bool(value) { self.invert_x = value },
+            "invert_y" => if let Some(value) = parse_bool(value) { self.invert_y = value },
+            "swap_buttons" => if let Some(value) = parse_bool(value) { self.swap_buttons = value },
+            _ => {}
+        }
+    }
+}
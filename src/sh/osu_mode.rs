@@ -0,0 +1,107 @@
+use super::clients::ClientId;
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_parse_osu_modes(){
+    assert_eq!(OsuMode::from_name("taiko"), Some(OsuMode::Taiko));
+    assert_eq!(OsuMode::from_name("mania7"), Some(OsuMode::Mania(7)));
+    assert_eq!(OsuMode::from_name("mania11"), None);
+    assert_eq!(OsuMode::Mania(10).name(), "mania10");
+}
+
+#[test]
+fn should_read_configured_keys(){
+    let config = ConfigFile::parse("[osu]\nstandard = a, s\nmania4 = q,w\n");
+
+    assert_eq!(OsuMode::Standard.keys(&config), vec!["a", "s"]);
+    // A layout with the wrong number of keys falls back to the default one.
+    assert_eq!(OsuMode::Mania(4).keys(&config), vec!["d", "f", "j", "k"]);
+    assert_eq!(OsuMode::Taiko.keys(&config).len(), 4);
+}
+
+const SECTION: &str = "osu";
+
+/// Game mode of an osu! client, which decides how many keys its mask has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OsuMode {
+    /// osu!standard, two keys.
+    Standard,
+    /// osu!taiko, left kat, left don, right don and right kat.
+    Taiko,
+    /// osu!mania with 4 to 10 keys, from left to right.
+    Mania(u8),
+}
+
+impl OsuMode {
+    pub const ALL: [OsuMode; 9] = [
+        OsuMode::Standard,
+        OsuMode::Taiko,
+        OsuMode::Mania(4),
+        OsuMode::Mania(5),
+        OsuMode::Mania(6),
+        OsuMode::Mania(7),
+        OsuMode::Mania(8),
+        OsuMode::Mania(9),
+        OsuMode::Mania(10),
+    ];
+
+    pub fn from_name(name: &str) -> Option<OsuMode>{
+        OsuMode::ALL.into_iter().find(|mode| mode.name() == name.trim())
+    }
+
+    /// Name of the mode as used by `OSU_MODE` and the `[osu]` section.
+    pub fn name(&self) -> String {
+        match self {
+            OsuMode::Standard => "standard".to_string(),
+            OsuMode::Taiko => "taiko".to_string(),
+            OsuMode::Mania(keys) => format!("mania{}", keys),
+        }
+    }
+
+    /// The mode new clients start in, `mode` in the `[osu]` section.
+    pub fn from_config(config: &ConfigFile) -> OsuMode {
+        config.get(SECTION, "mode").and_then(OsuMode::from_name).unwrap_or(OsuMode::Standard)
+    }
+
+    /// Names of the keys for the bits of the mask, from the `[osu]` section, e.g.
+    /// `mania4 = d,f,j,k`.
+    pub fn keys(&self, config: &ConfigFile) -> Vec<String>{
+        let default = self.default_keys();
+
+        if let Some(keys) = config.get(SECTION, &self.name()) {
+            let keys: Vec<String> = keys.split(',').map(|key| key.trim().to_string()).collect();
+            if keys.len() == default.len() {
+                return keys;
+            }
+            println!("The osu! {} layout needs {} keys, using the default one.", self.name(), default.len());
+        }
+
+        default.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn default_keys(&self) -> &'static [&'static str]{
+        match self {
+            OsuMode::Standard => &["z", "x"],
+            OsuMode::Taiko => &["d", "f", "j", "k"],
+            OsuMode::Mania(4) => &["d", "f", "j", "k"],
+            OsuMode::Mania(5) => &["d", "f", "space", "j", "k"],
+            OsuMode::Mania(6) => &["s", "d", "f", "j", "k", "l"],
+            OsuMode::Mania(7) => &["s", "d", "f", "space", "j", "k", "l"],
+            OsuMode::Mania(8) => &["a", "s", "d", "f", "j", "k", "l", "semicolon"],
+            OsuMode::Mania(9) => &["a", "s", "d", "f", "space", "j", "k", "l", "semicolon"],
+            OsuMode::Mania(_) => &["a", "s", "d", "f", "v", "n", "j", "k", "l", "semicolon"],
+        }
+    }
+}
+
+/// Messages handled by the osu! thread, tagged with the client that sent them.
+pub enum OsuMessage {
+    /// Data of the `OSU` protocol, a mask of the pressed keys.
+    Keys(ClientId, String),
+    /// Data of the `OSU_MODE` protocol, the name of an `OsuMode`.
+    Mode(ClientId, String),
+    /// `OSU_STATS` asks for the client's tap statistics.
+    Stats(ClientId),
+    /// The client disconnected, its keys are released.
+    Disconnected(ClientId),
+}
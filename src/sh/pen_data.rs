@@ -0,0 +1,60 @@
+#[test]
+fn should_parse_pen_data(){
+    let data = PenData::new("2;0.5;0.25;0.75;-30;12.5;1".to_string());
+
+    assert_eq!(data.tool, PenTool::Rubber);
+    assert_eq!(data.x, 0.5);
+    assert_eq!(data.pressure, 0.75);
+    assert_eq!(data.tilt_x, -30.0);
+    assert_eq!(data.button_state, 1);
+    assert!(data.is_touching());
+
+    assert_eq!(PenData::new("7".to_string()).tool, PenTool::None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PenTool {
+    /// The stylus left the proximity of the tablet.
+    None,
+    Pen,
+    Rubber,
+}
+
+/// Data of the `PEN` protocol: `tool;x;y;pressure;tilt_x;tilt_y;buttons`
+///
+/// `tool` is 0 when out of proximity, 1 for the pen tip and 2 for the eraser.
+/// Position and pressure are normalized to `0.0..=1.0`, tilt is in degrees.
+/// A tool in proximity with no pressure is hovering.
+pub struct PenData {
+    pub tool: PenTool,
+    pub x: f32,
+    pub y: f32,
+    pub pressure: f32,
+    pub tilt_x: f32,
+    pub tilt_y: f32,
+    pub button_state: u8,
+}
+
+impl PenData {
+    pub fn new(data_string: String) -> Self {
+
+        let mut split = data_string.split(';');
+        let tool = match split.next().unwrap_or("0").parse::<u8>().unwrap_or(0u8) {
+            1 => PenTool::Pen,
+            2 => PenTool::Rubber,
+            _ => PenTool::None,
+        };
+        let x = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(0.0, 1.0);
+        let y = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(0.0, 1.0);
+        let pressure = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(0.0, 1.0);
+        let tilt_x = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(-90.0, 90.0);
+        let tilt_y = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(-90.0, 90.0);
+        let buttons = split.next().unwrap_or("0").parse::<u8>().unwrap_or(0u8);
+
+        Self{ tool, x, y, pressure, tilt_x, tilt_y, button_state: buttons }
+    }
+
+    pub fn is_touching(&self) -> bool {
+        self.tool != PenTool::None && self.pressure > 0.0
+    }
+}
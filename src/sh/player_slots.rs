@@ -0,0 +1,71 @@
+use super::clients::ClientId;
+
+#[test]
+fn should_give_clients_the_first_free_slot(){
+    let mut slots = PlayerSlots::default();
+
+    assert_eq!(slots.claim(10, None), Some(1));
+    assert_eq!(slots.claim(11, None), Some(2));
+    assert_eq!(slots.claim(10, None), Some(1));
+
+    assert_eq!(slots.release(10), Some(1));
+    assert_eq!(slots.claim(12, None), Some(1));
+    assert_eq!(slots.player_of(11), Some(2));
+}
+
+#[test]
+fn should_claim_requested_slot_only_when_free(){
+    let mut slots = PlayerSlots::default();
+
+    assert_eq!(slots.claim(10, Some(3)), Some(3));
+    assert_eq!(slots.claim(11, Some(3)), None);
+    assert_eq!(slots.claim(11, Some(MAX_PLAYERS + 1)), None);
+
+    // Moving to another slot frees the previous one.
+    assert_eq!(slots.claim(10, Some(4)), Some(4));
+    assert_eq!(slots.claim(11, Some(3)), Some(3));
+}
+
+pub const MAX_PLAYERS: usize = 4;
+
+/// Which client holds which player slot, players are numbered from 1.
+#[derive(Debug, Default)]
+pub struct PlayerSlots {
+    owners: [Option<ClientId>; MAX_PLAYERS],
+}
+
+impl PlayerSlots {
+    /// Claims the `requested` slot, or the first free one when `None`, and returns the
+    /// player number. A client that already holds a slot keeps it unless it asks for a
+    /// different one.
+    pub fn claim(&mut self, client: ClientId, requested: Option<usize>) -> Option<usize>{
+        let current = self.player_of(client);
+
+        let player = match requested {
+            Some(player) if current == Some(player) => return current,
+            Some(player) => {
+                if player == 0 || player > MAX_PLAYERS || self.owners[player - 1].is_some() {
+                    return None;
+                }
+                player
+            }
+            None if current.is_some() => return current,
+            None => self.owners.iter().position(|owner| owner.is_none())? + 1,
+        };
+
+        self.release(client);
+        self.owners[player - 1] = Some(client);
+        Some(player)
+    }
+
+    /// Frees the slot held by the client and returns its player number.
+    pub fn release(&mut self, client: ClientId) -> Option<usize>{
+        let player = self.player_of(client)?;
+        self.owners[player - 1] = None;
+        Some(player)
+    }
+
+    pub fn player_of(&self, client: ClientId) -> Option<usize>{
+        self.owners.iter().position(|owner| *owner == Some(client)).map(|index| index + 1)
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+#[test]
+fn should_spread_delta_over_steps_without_losing_motion(){
+    let mut smoothing = PointerSmoothing::new(1000, Duration::from_millis(4));
+    smoothing.push(10.0, -3.0);
+
+    let mut total = (0, 0);
+    while let Some((x, y)) = smoothing.step() {
+        total = (total.0 + x, total.1 + y);
+    }
+
+    assert_eq!(total, (10, -3));
+    assert!(smoothing.is_idle());
+}
+
+#[test]
+fn should_flush_pending_motion_at_once(){
+    let mut smoothing = PointerSmoothing::new(1000, Duration::from_millis(8));
+    smoothing.push(7.0, 7.0);
+    let _ = smoothing.step();
+
+    let (x, y) = smoothing.flush().unwrap();
+    assert!(x > 0 && y > 0);
+    assert!(smoothing.is_idle());
+    assert!(smoothing.flush().is_none());
+}
+
+/// Spreads pointer deltas over evenly timed sub-steps.
+///
+/// Every pushed delta is added to the motion that is still pending and the whole
+/// amount is then paid out over the number of steps that fit in the latency budget.
+/// Fractions of a pixel are carried over between steps so no motion is lost.
+#[derive(Debug)]
+pub struct PointerSmoothing {
+    interval: Duration,
+    steps: u32,
+    remaining: u32,
+    pending_x: f32,
+    pending_y: f32,
+    carry_x: f32,
+    carry_y: f32,
+}
+
+impl PointerSmoothing {
+    pub fn new(rate: u32, latency: Duration) -> Self {
+        let rate = rate.max(1);
+        let interval = Duration::from_secs_f64(1.0 / rate as f64);
+        let steps = (latency.as_secs_f64() * rate as f64).round().max(1.0) as u32;
+
+        Self{ interval, steps, remaining: 0, pending_x: 0.0, pending_y: 0.0, carry_x: 0.0, carry_y: 0.0 }
+    }
+
+    /// Time between two consecutive sub-steps.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn push(&mut self, delta_x: f32, delta_y: f32){
+        self.pending_x += delta_x;
+        self.pending_y += delta_y;
+        self.remaining = self.steps;
+    }
+
+    /// Returns the motion for the next sub-step, `None` once everything was paid out.
+    pub fn step(&mut self) -> Option<(i32, i32)>{
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let step_x = self.pending_x / self.remaining as f32;
+        let step_y = self.pending_y / self.remaining as f32;
+        self.pending_x -= step_x;
+        self.pending_y -= step_y;
+        self.remaining -= 1;
+
+        Some(self.carry(step_x, step_y))
+    }
+
+    /// Pays out all pending motion at once, e.g. before a button changes its state.
+    pub fn flush(&mut self) -> Option<(i32, i32)>{
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let (step_x, step_y) = (self.pending_x, self.pending_y);
+        self.pending_x = 0.0;
+        self.pending_y = 0.0;
+        self.remaining = 0;
+
+        Some(self.carry(step_x, step_y))
+    }
+
+    fn carry(&mut self, step_x: f32, step_y: f32) -> (i32, i32){
+        self.carry_x += step_x;
+        self.carry_y += step_y;
+
+        let x = self.carry_x.round();
+        let y = self.carry_y.round();
+        self.carry_x -= x;
+        self.carry_y -= y;
+
+        (x as i32, y as i32)
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+#[test]
+fn should_play_uploaded_effects_with_gain(){
+    let mut effects = RumbleEffects::default();
+    effects.upload(3, u16::MAX, u16::MAX / 2, 250);
+    effects.set_gain(u16::MAX / 2);
+
+    assert_eq!(effects.play(3, 1).unwrap().to_message(), "RUMBLE|0.5;0.25;250");
+    assert_eq!(effects.play(3, 0).unwrap().to_message(), "RUMBLE|0;0;0");
+
+    effects.erase(3);
+    assert!(effects.play(3, 1).is_none());
+}
+
+#[test]
+fn should_handle_endless_effects(){
+    let mut effects = RumbleEffects::default();
+    effects.upload(1, u16::MAX, 0, 1000);
+    effects.upload(2, u16::MAX, 0, 0);
+
+    // Looping forever is asked for with the largest count.
+    assert_eq!(effects.play(1, i32::MAX).unwrap().duration_ms, Some(u32::MAX));
+    assert_eq!(effects.play(1, -5).unwrap(), Rumble::stop());
+    assert_eq!(effects.play(2, 1).unwrap().to_message(), "RUMBLE|1;0;-1");
+}
+
+/// A rumble command for the client, strengths are in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rumble {
+    pub strong: f32,
+    pub weak: f32,
+    /// `None` rumbles until the next command.
+    pub duration_ms: Option<u32>,
+}
+
+impl Rumble {
+    pub fn stop() -> Self {
+        Self{ strong: 0.0, weak: 0.0, duration_ms: Some(0) }
+    }
+
+    /// Formats the command as sent to the client: `RUMBLE|strong;weak;duration_ms`, a
+    /// duration of 0 stops rumbling and -1 rumbles until the next command.
+    pub fn to_message(&self) -> String {
+        let round = |value: f32| (value * 1000.0).round() / 1000.0;
+        let duration = self.duration_ms.map(|duration| duration as i64).unwrap_or(-1);
+        format!("RUMBLE|{};{};{}", round(self.strong), round(self.weak), duration)
+    }
+}
+
+/// Rumble effects uploaded to a virtual device, keyed by their effect id.
+#[derive(Debug)]
+pub struct RumbleEffects {
+    effects: HashMap<i16, (u16, u16, u16)>,
+    gain: u16,
+}
+
+impl Default for RumbleEffects {
+    fn default() -> Self {
+        Self{ effects: HashMap::new(), gain: u16::MAX }
+    }
+}
+
+impl RumbleEffects {
+    pub fn upload(&mut self, id: i16, strong_magnitude: u16, weak_magnitude: u16, length_ms: u16){
+        self.effects.insert(id, (strong_magnitude, weak_magnitude, length_ms));
+    }
+
+    pub fn erase(&mut self, id: i16){
+        self.effects.remove(&id);
+    }
+
+    pub fn set_gain(&mut self, gain: u16){
+        self.gain = gain;
+    }
+
+    /// Returns the command for a play request, a `count` of 0 stops the effect. An effect
+    /// with a length of 0 plays until it is stopped.
+    pub fn play(&self, id: i16, count: i32) -> Option<Rumble>{
+        let (strong, weak, length) = self.effects.get(&id)?;
+
+        if count <= 0 {
+            return Some(Rumble::stop());
+        }
+
+        let gain = self.gain as f32 / u16::MAX as f32;
+        Some(Rumble{
+            strong: *strong as f32 / u16::MAX as f32 * gain,
+            weak: *weak as f32 / u16::MAX as f32 * gain,
+            duration_ms: match length {
+                0 => None,
+                length => Some((*length as u32).saturating_mul(count.max(0) as u32)),
+            },
+        })
+    }
+}
@@ -1,9 +1,26 @@
 use std::sync::mpsc;
 
+use super::bitmask_device::BitmaskSenders;
+use super::gamepad_data::GamepadMessage;
+use super::gesture_recognizer::StrokeMessage;
+use super::keyboard_data::KeyboardMessage;
+use super::mouse_data::MouseMessage;
+use super::osu_mode::OsuMessage;
+use super::sensor_data::SensorMessage;
+
 pub struct Senders {
-    pub osu_sender: mpsc::Sender<String>,
-    pub keyboard_sender: mpsc::Sender<String>,
-    pub mouse_sender: mpsc::Sender<String>,
+    pub osu_sender: mpsc::Sender<OsuMessage>,
+    pub keyboard_sender: mpsc::Sender<KeyboardMessage>,
+    pub mouse_sender: mpsc::Sender<MouseMessage>,
+    pub touchpad_sender: mpsc::Sender<String>,
+    pub pen_sender: mpsc::Sender<String>,
+    pub gamepad_sender: mpsc::Sender<GamepadMessage>,
+    pub sensor_sender: mpsc::Sender<SensorMessage>,
+    pub stroke_sender: mpsc::Sender<StrokeMessage>,
+    pub dial_sender: mpsc::Sender<String>,
+    pub macro_sender: mpsc::Sender<String>,
+    /// Devices defined in the config, by their protocol.
+    pub bitmask_senders: BitmaskSenders,
 }
 
 impl Clone for Senders {
@@ -12,6 +29,28 @@ impl Clone for Senders {
             osu_sender: self.osu_sender.clone(),
             keyboard_sender: self.keyboard_sender.clone(),
             mouse_sender: self.mouse_sender.clone(),
+            touchpad_sender: self.touchpad_sender.clone(),
+            pen_sender: self.pen_sender.clone(),
+            gamepad_sender: self.gamepad_sender.clone(),
+            sensor_sender: self.sensor_sender.clone(),
+            stroke_sender: self.stroke_sender.clone(),
+            dial_sender: self.dial_sender.clone(),
+            macro_sender: self.macro_sender.clone(),
+            bitmask_senders: self.bitmask_senders.clone(),
         }
     }
 }
+
+/// Receiving ends of the channels in `Senders`, handed over to the device threads.
+pub struct Receivers {
+    pub osu_receiver: mpsc::Receiver<OsuMessage>,
+    pub keyboard_receiver: mpsc::Receiver<KeyboardMessage>,
+    pub mouse_receiver: mpsc::Receiver<MouseMessage>,
+    pub touchpad_receiver: mpsc::Receiver<String>,
+    pub pen_receiver: mpsc::Receiver<String>,
+    pub gamepad_receiver: mpsc::Receiver<GamepadMessage>,
+    pub sensor_receiver: mpsc::Receiver<SensorMessage>,
+    pub stroke_receiver: mpsc::Receiver<StrokeMessage>,
+    pub dial_receiver: mpsc::Receiver<String>,
+    pub macro_receiver: mpsc::Receiver<String>,
+}
@@ -0,0 +1,48 @@
+use super::clients::ClientId;
+
+#[test]
+fn should_parse_sensor_data(){
+    let data = SensorData::new("0.1;-0.2;0.3;0;9.81;0;1;1500".to_string());
+
+    assert_eq!(data.gyro, [0.1, -0.2, 0.3]);
+    assert_eq!(data.accel, [0.0, 9.81, 0.0]);
+    assert_eq!(data.button_state, 1);
+    assert_eq!(data.timestamp, Some(1500));
+
+    assert_eq!(SensorData::new("0;0;0".to_string()).timestamp, None);
+}
+
+/// Data of the `SENSOR` protocol: `gx;gy;gz;ax;ay;az;buttons;timestamp`
+///
+/// Angular velocity is in rad/s and acceleration in m/s², both in the phone's frame:
+/// x to the right of the screen, y to its top and z out of the screen. `timestamp` is
+/// an optional sample time in milliseconds, without it the arrival time is used.
+pub struct SensorData {
+    pub gyro: [f32; 3],
+    pub accel: [f32; 3],
+    pub button_state: u8,
+    pub timestamp: Option<u64>,
+}
+
+impl SensorData {
+    pub fn new(data_string: String) -> Self {
+
+        let mut split = data_string.split(';');
+        let mut next_f32 = || split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32);
+
+        let gyro = [next_f32(), next_f32(), next_f32()];
+        let accel = [next_f32(), next_f32(), next_f32()];
+        let buttons = split.next().unwrap_or("0").parse::<u8>().unwrap_or(0u8);
+        let timestamp = split.next().and_then(|value| value.parse::<u64>().ok());
+
+        Self{ gyro, accel, button_state: buttons, timestamp }
+    }
+}
+
+/// Messages handled by the sensor thread, tagged with the client that sent them.
+pub enum SensorMessage {
+    /// Data of the `SENSOR` protocol, or `calibrate` to measure the gyroscope bias.
+    Data(ClientId, String),
+    /// The client disconnected, its filter state can be dropped.
+    Disconnected(ClientId),
+}
@@ -0,0 +1,224 @@
+use std::f32::consts::PI;
+
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_calibrate_gyro_bias_while_still(){
+    let mut filter = SensorFilter::new(SensorSettings{ cutoff: 0.0, ..SensorSettings::default() }, [0.0; 3]);
+
+    filter.start_calibration();
+    let mut result = None;
+    for step in 0..=25 {
+        result = filter.update([0.1, -0.2, 0.05], [0.0, 0.0, 9.81], step as f64 * 0.1);
+        if result.is_some() { break; }
+    }
+
+    match result {
+        Some(CalibrationResult::Done(bias)) => assert!((bias[1] + 0.2).abs() < 0.0001),
+        _ => panic!("calibration did not finish"),
+    }
+    filter.update([0.1, -0.2, 0.05], [0.0, 0.0, 9.81], 2.6);
+    assert!(filter.gyro().iter().all(|value| value.abs() < 0.0001));
+}
+
+#[test]
+fn should_fail_calibration_when_moved(){
+    let mut filter = SensorFilter::new(SensorSettings::default(), [0.0; 3]);
+
+    filter.start_calibration();
+    filter.update([0.0, 0.0, 0.0], [0.0, 0.0, 9.81], 0.0);
+    filter.update([2.0, 0.0, 0.0], [0.0, 0.0, 9.81], 1.0);
+    assert_eq!(filter.update([0.0, 0.0, 0.0], [0.0, 0.0, 9.81], 2.0), Some(CalibrationResult::Failed));
+}
+
+#[test]
+fn should_fuse_orientation_from_gravity(){
+    let mut filter = SensorFilter::new(SensorSettings{ cutoff: 0.0, fusion_weight: 0.5, ..SensorSettings::default() }, [0.0; 3]);
+
+    // Lying flat, then the right edge tipped down by 45 degrees.
+    filter.update([0.0; 3], [0.0, 0.0, 9.81], 0.0);
+    assert_eq!(filter.orientation(), (0.0, 0.0));
+    for step in 1..20 {
+        filter.update([0.0; 3], [-6.9367, 0.0, 6.9367], step as f64 * 0.01);
+    }
+    assert!((filter.orientation().0 - 45.0).abs() < 0.01);
+}
+
+#[test]
+fn should_smooth_with_low_pass(){
+    let mut filter = SensorFilter::new(SensorSettings{ cutoff: 1.0, ..SensorSettings::default() }, [0.0; 3]);
+
+    filter.update([0.0; 3], [0.0, 0.0, 9.81], 0.0);
+    filter.update([1.0, 0.0, 0.0], [0.0, 0.0, 9.81], 0.01);
+    assert!(filter.gyro()[0] > 0.0 && filter.gyro()[0] < 0.1);
+}
+
+const SECTION: &str = "sensor";
+/// Gyroscope biases measured by `SENSOR|calibrate`, keyed by the client's address.
+pub const CALIBRATION_SECTION: &str = "sensor_calibration";
+
+// How long the phone has to be held still while calibrating, in seconds.
+const CALIBRATION_TIME: f64 = 2.0;
+// Angular velocity in rad/s away from the average that counts as the phone being moved.
+const STILL_THRESHOLD: f32 = 0.1;
+// Longer gaps between samples are treated as a pause, not as a long rotation.
+const MAX_STEP: f64 = 0.05;
+
+/// What the fused sensor data drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorTarget {
+    /// The air mouse, see `AirMouse`.
+    Mouse,
+    /// The client's gamepad, with the orientation sent as `TILT`.
+    Gamepad,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorSettings {
+    /// Cut-off frequency in Hz of the low-pass filter, 0 disables it.
+    pub cutoff: f32,
+    /// How much the orientation trusts the integrated gyroscope over the gravity direction.
+    pub fusion_weight: f32,
+    pub target: SensorTarget,
+}
+
+impl Default for SensorSettings {
+    fn default() -> Self {
+        Self{ cutoff: 10.0, fusion_weight: 0.98, target: SensorTarget::Mouse }
+    }
+}
+
+impl SensorSettings {
+    pub fn from_config(config: &ConfigFile) -> Self {
+        let default = Self::default();
+        let target = match config.get(SECTION, "target") {
+            Some("gamepad") => SensorTarget::Gamepad,
+            _ => default.target,
+        };
+
+        Self{
+            cutoff: config.get_or(SECTION, "cutoff", default.cutoff).max(0.0),
+            fusion_weight: config.get_or(SECTION, "fusion_weight", default.fusion_weight).clamp(0.0, 1.0),
+            target,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalibrationResult {
+    /// The measured gyroscope bias, already applied to the following samples.
+    Done([f32; 3]),
+    /// The phone was moved while calibrating, the previous bias is kept.
+    Failed,
+}
+
+/// Cleans up the samples of one phone: removes the calibrated gyroscope bias, smooths
+/// both sensors with a low-pass filter and fuses them into an orientation with a
+/// complementary filter.
+#[derive(Debug)]
+pub struct SensorFilter {
+    settings: SensorSettings,
+    bias: [f32; 3],
+    calibration: Option<(Option<f64>, Vec<[f32; 3]>)>,
+    last_time: Option<f64>,
+    gyro: [f32; 3],
+    accel: [f32; 3],
+    roll: f32,
+    pitch: f32,
+}
+
+impl SensorFilter {
+    pub fn new(settings: SensorSettings, bias: [f32; 3]) -> Self {
+        Self{ settings, bias, calibration: None, last_time: None, gyro: [0.0; 3], accel: [0.0; 3], roll: 0.0, pitch: 0.0 }
+    }
+
+    pub fn settings(&self) -> &SensorSettings {
+        &self.settings
+    }
+
+    /// Measures the gyroscope bias over the next two seconds of samples.
+    pub fn start_calibration(&mut self){
+        self.calibration = Some((None, vec![]));
+    }
+
+    /// Feeds one raw sample taken at `time` seconds, returns the outcome of a running
+    /// calibration once it is finished.
+    pub fn update(&mut self, gyro: [f32; 3], accel: [f32; 3], time: f64) -> Option<CalibrationResult>{
+        let result = self.calibrate(gyro, time);
+
+        let gyro = [gyro[0] - self.bias[0], gyro[1] - self.bias[1], gyro[2] - self.bias[2]];
+
+        let delta_time = match self.last_time {
+            Some(last_time) => (time - last_time).clamp(0.0, MAX_STEP) as f32,
+            None => {
+                self.last_time = Some(time);
+                self.gyro = gyro;
+                self.accel = accel;
+                (self.roll, self.pitch) = gravity_angles(accel);
+                return result;
+            }
+        };
+        self.last_time = Some(time);
+
+        let alpha = if self.settings.cutoff > 0.0 {
+            let rc = 1.0 / (2.0 * PI * self.settings.cutoff);
+            delta_time / (rc + delta_time)
+        } else {
+            1.0
+        };
+        for axis in 0..3 {
+            self.gyro[axis] += alpha * (gyro[axis] - self.gyro[axis]);
+            self.accel[axis] += alpha * (accel[axis] - self.accel[axis]);
+        }
+
+        let (roll, pitch) = gravity_angles(self.accel);
+        let weight = self.settings.fusion_weight;
+        self.roll = weight * (self.roll + self.gyro[1] * delta_time) + (1.0 - weight) * roll;
+        self.pitch = weight * (self.pitch + self.gyro[0] * delta_time) + (1.0 - weight) * pitch;
+
+        result
+    }
+
+    /// Filtered angular velocity in rad/s.
+    pub fn gyro(&self) -> [f32; 3] {
+        self.gyro
+    }
+
+    /// Roll (right edge down is positive) and pitch (top up is positive) in degrees.
+    pub fn orientation(&self) -> (f32, f32) {
+        (self.roll.to_degrees(), self.pitch.to_degrees())
+    }
+
+    fn calibrate(&mut self, gyro: [f32; 3], time: f64) -> Option<CalibrationResult>{
+        let (started, samples) = self.calibration.as_mut()?;
+        let started = *started.get_or_insert(time);
+        samples.push(gyro);
+
+        if time - started < CALIBRATION_TIME {
+            return None;
+        }
+
+        let samples = self.calibration.take()?.1;
+        let count = samples.len() as f32;
+        let mut mean = [0.0; 3];
+        for sample in samples.iter() {
+            for axis in 0..3 {
+                mean[axis] += sample[axis] / count;
+            }
+        }
+
+        let moved = samples.iter()
+            .any(|sample| (0..3).any(|axis| (sample[axis] - mean[axis]).abs() > STILL_THRESHOLD));
+        if moved || samples.len() < 2 {
+            return Some(CalibrationResult::Failed);
+        }
+
+        self.bias = mean;
+        Some(CalibrationResult::Done(mean))
+    }
+}
+
+/// Roll and pitch in radians of a phone at rest, from the direction of gravity.
+fn gravity_angles(accel: [f32; 3]) -> (f32, f32){
+    ((-accel[0]).atan2(accel[2]), accel[1].atan2(accel[2]))
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use super::air_mouse::{AirMouse, AirMouseSettings};
+use super::clients::{ClientId, Clients};
+use super::config_file::SharedConfig;
+use super::config_watcher::ReloadTracker;
+use super::gamepad_data::GamepadMessage;
+use super::mouse_data::MouseMessage;
+use super::sensor_data::{SensorData, SensorMessage};
+use super::sensor_fusion::{CalibrationResult, SensorFilter, SensorSettings, SensorTarget, CALIBRATION_SECTION};
+
+/// Sensor state of one connected phone.
+struct ClientSensor {
+    filter: SensorFilter,
+    air_mouse: AirMouse,
+    // Calibrations are stored by the address of the phone.
+    address: Option<String>,
+}
+
+/// Filters `SENSOR` samples per client and maps them onto the virtual mouse or, with
+/// `target = gamepad`, onto the client's gamepad as tilt. Bit 0 of the buttons is the
+/// air mouse clutch.
+pub fn start_sensor_input(receiver: mpsc::Receiver<SensorMessage>, mouse_sender: mpsc::Sender<MouseMessage>, gamepad_sender: mpsc::Sender<GamepadMessage>, config: SharedConfig, clients: Clients){
+
+    let started = Instant::now();
+    let mut sensors: HashMap<ClientId, ClientSensor> = HashMap::new();
+    let mut reloads = ReloadTracker::new(&config);
+
+    for message in receiver.iter() {
+        // Filters are created again with the new settings on the next sample.
+        if reloads.reloaded(&config) {
+            sensors.clear();
+        }
+
+        let (client, message) = match message {
+            SensorMessage::Data(client, message) => (client, message),
+            SensorMessage::Disconnected(client) => {
+                sensors.remove(&client);
+                continue;
+            }
+        };
+
+        let sensor = sensors.entry(client).or_insert_with(|| {
+            let address = clients.address(client).map(|address| address.to_string());
+            let config = config.lock().unwrap();
+            let bias = address.as_deref()
+                .and_then(|address| config.get(CALIBRATION_SECTION, address))
+                .map(parse_bias)
+                .unwrap_or([0.0; 3]);
+
+            ClientSensor{
+                filter: SensorFilter::new(SensorSettings::from_config(&config), bias),
+                air_mouse: AirMouse::new(AirMouseSettings::from_config(&config)),
+                address,
+            }
+        });
+
+        if message.trim() == "calibrate" {
+            sensor.filter.start_calibration();
+            continue;
+        }
+
+        let sensor_state = SensorData::new(message);
+
+        let time = match sensor_state.timestamp {
+            Some(timestamp) => timestamp as f64 / 1000.0,
+            None => started.elapsed().as_secs_f64(),
+        };
+
+        match sensor.filter.update(sensor_state.gyro, sensor_state.accel, time) {
+            Some(CalibrationResult::Done(bias)) => {
+                if let Some(address) = &sensor.address {
+                    let mut config = config.lock().unwrap();
+                    config.set(CALIBRATION_SECTION, address, &format!("{};{};{}", bias[0], bias[1], bias[2]));
+                    if let Err(error) = config.save() {
+                        println!("Failed to save sensor calibration.");
+                        println!("{}", error);
+                    }
+                }
+                clients.send(client, "CALIBRATION|done");
+            }
+            Some(CalibrationResult::Failed) => clients.send(client, "CALIBRATION|failed"),
+            None => {}
+        }
+
+        match sensor.filter.settings().target {
+            SensorTarget::Mouse => {
+                let clutch = sensor_state.button_state & 1 > 0;
+                if let Some((x, y)) = sensor.air_mouse.update(sensor.filter.gyro(), clutch, time) {
+                    let _ = mouse_sender.send(MouseMessage::Motion(x, y));
+                }
+            }
+            SensorTarget::Gamepad => {
+                let (roll, pitch) = sensor.filter.orientation();
+                let _ = gamepad_sender.send(GamepadMessage::Tilt(client, format!("{};{}", roll, pitch)));
+            }
+        }
+    }
+}
+
+fn parse_bias(value: &str) -> [f32; 3]{
+    let mut split = value.split(';');
+    let mut next_f32 = || split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32);
+    [next_f32(), next_f32(), next_f32()]
+}
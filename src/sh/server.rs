@@ -2,11 +2,22 @@ use std::io::{BufRead, BufReader};
 use std::net::{TcpListener, TcpStream};
 use std::thread;
 
+use super::bitmask_device::BitmaskMessage;
+use super::clients::{ClientId, Clients};
+use super::gamepad_data::GamepadMessage;
+use super::gesture_recognizer::StrokeMessage;
+use super::keyboard_data::KeyboardMessage;
+use super::mouse_data::MouseMessage;
+use super::osu_mode::OsuMessage;
+use super::sensor_data::SensorMessage;
 use super::senders::Senders;
+use super::session::SessionRecorder;
 
 pub struct Server {
     listener: Option<TcpListener>,
     senders: Option<Senders>,
+    clients: Clients,
+    recorder: Option<SessionRecorder>,
 }
 
 impl Server {
@@ -16,12 +27,12 @@ impl Server {
         match listener {
             Ok(listener) => {
                 println!("Socket creation successful.");
-                return Self{ listener: Some(listener), senders: None }
+                Self{ listener: Some(listener), senders: None, clients: Clients::default(), recorder: None }
             }
             Err(error) => {
                 println!("Failed to create socket. Is the port in use?");
                 println!("{}", error);
-                Self{ listener: None, senders: None }
+                Self{ listener: None, senders: None, clients: Clients::default(), recorder: None }
             }
         }
     }
@@ -30,16 +41,22 @@ impl Server {
         self.senders = Some(senders);
     }
 
+    pub fn set_clients(&mut self, clients: Clients){
+        self.clients = clients;
+    }
+
+    pub fn set_recorder(&mut self, recorder: SessionRecorder){
+        self.recorder = Some(recorder);
+    }
+
     pub fn start(&mut self){
         println!("Starting a server…");
         match &self.listener {
             Some(listener) => {
                 println!("The server has started.");
-                for stream in listener.incoming() {
-                    if let Ok(stream) = stream{
-                        if let Some(senders) = &self.senders{
-                            self.create_connection(stream, senders.clone());
-                        }
+                for stream in listener.incoming().flatten() {
+                    if let Some(senders) = &self.senders{
+                        self.create_connection(stream, senders.clone());
                     }
                 }
             },
@@ -50,21 +67,52 @@ impl Server {
     fn create_connection(&self, connection: TcpStream, senders: Senders){
         println!("Device connected from: {}", connection.peer_addr().unwrap());
 
+        let clients = self.clients.clone();
+        let client = clients.add(&connection);
+        let recorder = self.recorder.clone();
+        let _ = senders.keyboard_sender.send(KeyboardMessage::Connected(client));
+
         thread::spawn(move||{
             let mut reader = BufReader::new(&connection);
             'connection: loop {
                 let mut msg = String::new();
-                if let Ok(bytes) = reader.read_line(&mut msg){
-                    if bytes <= 0  { break 'connection; }
-                    Server::sort_and_run_message(msg.trim(), &senders);
+                match reader.read_line(&mut msg) {
+                    Ok(0) | Err(_) => break 'connection,
+                    Ok(_) => {
+                        if let Some(recorder) = &recorder {
+                            recorder.record(client, Some(msg.trim()));
+                        }
+                        Server::sort_and_run_message(msg.trim(), client, &senders)
+                    }
                 }
             }
 
+            if let Some(recorder) = &recorder {
+                recorder.record(client, None);
+            }
+            Server::disconnect(client, &senders);
+            clients.remove(client);
             println!("Device disconnected.");
         });
     }
 
-    fn sort_and_run_message(message: &str, senders: &Senders){
+    /// Lets the device threads drop the state they keep for the client.
+    pub fn disconnect(client: ClientId, senders: &Senders){
+        let _ = senders.osu_sender.send(OsuMessage::Disconnected(client));
+        let _ = senders.gamepad_sender.send(GamepadMessage::Disconnected(client));
+        let _ = senders.sensor_sender.send(SensorMessage::Disconnected(client));
+        let _ = senders.stroke_sender.send(StrokeMessage::Disconnected(client));
+        for sender in senders.bitmask_senders.lock().unwrap().values() {
+            let _ = sender.send(BitmaskMessage::Disconnected(client));
+        }
+    }
+
+    /// Whether the protocol is handled by the server itself, rather than a configured device.
+    pub fn is_built_in_protocol(protocol: &str) -> bool {
+        !matches!(Protocol::from_str(protocol), Protocol::Unknown(_))
+    }
+
+    pub fn sort_and_run_message(message: &str, client: ClientId, senders: &Senders){
         let mut split = message.split('|');
         let protocol = split.next();
         let data = split.next();
@@ -75,10 +123,26 @@ impl Server {
 
         if let Some(protocol) = protocol{
             match Protocol::from_str(protocol) {
-                Protocol::Osu => senders.osu_sender.send(data.unwrap().to_string()).unwrap(),
-                Protocol::Keyboard => senders.keyboard_sender.send(data.unwrap().to_string()).unwrap(),
-                Protocol::Mouse => senders.mouse_sender.send(data.unwrap().to_string()).unwrap(),
-                Protocol::Unknown(str) => println!("Unknown protocol: {}", str),
+                Protocol::Osu => senders.osu_sender.send(OsuMessage::Keys(client, data.unwrap().to_string())).unwrap(),
+                Protocol::OsuMode => senders.osu_sender.send(OsuMessage::Mode(client, data.unwrap().to_string())).unwrap(),
+                Protocol::OsuStats => senders.osu_sender.send(OsuMessage::Stats(client)).unwrap(),
+                Protocol::Keyboard => senders.keyboard_sender.send(KeyboardMessage::Keys(data.unwrap().to_string())).unwrap(),
+                Protocol::Stick => senders.keyboard_sender.send(KeyboardMessage::Stick(data.unwrap().to_string())).unwrap(),
+                Protocol::Mouse => senders.mouse_sender.send(MouseMessage::Data(data.unwrap().to_string())).unwrap(),
+                Protocol::MouseSettings => senders.mouse_sender.send(MouseMessage::Settings(data.unwrap().to_string())).unwrap(),
+                Protocol::Touchpad => senders.touchpad_sender.send(data.unwrap().to_string()).unwrap(),
+                Protocol::Pen => senders.pen_sender.send(data.unwrap().to_string()).unwrap(),
+                Protocol::Gamepad => senders.gamepad_sender.send(GamepadMessage::Data(client, data.unwrap().to_string())).unwrap(),
+                Protocol::Tilt => senders.gamepad_sender.send(GamepadMessage::Tilt(client, data.unwrap().to_string())).unwrap(),
+                Protocol::Player => senders.gamepad_sender.send(GamepadMessage::Claim(client, data.unwrap().to_string())).unwrap(),
+                Protocol::Sensor => senders.sensor_sender.send(SensorMessage::Data(client, data.unwrap().to_string())).unwrap(),
+                Protocol::Stroke => senders.stroke_sender.send(StrokeMessage::Frame(client, data.unwrap().to_string())).unwrap(),
+                Protocol::Dial => senders.dial_sender.send(data.unwrap().to_string()).unwrap(),
+                Protocol::Macro => senders.macro_sender.send(data.unwrap().to_string()).unwrap(),
+                Protocol::Unknown(str) => match senders.bitmask_senders.lock().unwrap().get(&str) {
+                    Some(sender) => { let _ = sender.send(BitmaskMessage::Data(client, data.unwrap().to_string())); }
+                    None => println!("Unknown protocol: {}", str),
+                },
             }
         }
     }
@@ -86,8 +150,21 @@ impl Server {
 
 enum Protocol{
     Osu,
+    OsuMode,
+    OsuStats,
     Keyboard,
+    Stick,
     Mouse,
+    MouseSettings,
+    Touchpad,
+    Pen,
+    Gamepad,
+    Tilt,
+    Player,
+    Sensor,
+    Stroke,
+    Dial,
+    Macro,
     Unknown(String),
 }
 
@@ -95,8 +172,21 @@ impl Protocol {
     fn from_str(str: &str) -> Protocol{
         match str{
             "OSU" => Protocol::Osu,
+            "OSU_MODE" => Protocol::OsuMode,
+            "OSU_STATS" => Protocol::OsuStats,
             "KEYBOARD" => Protocol::Keyboard,
+            "STICK" => Protocol::Stick,
             "MOUSE" => Protocol::Mouse,
+            "MOUSE_SETTINGS" => Protocol::MouseSettings,
+            "TOUCHPAD" => Protocol::Touchpad,
+            "PEN" => Protocol::Pen,
+            "GAMEPAD" => Protocol::Gamepad,
+            "TILT" => Protocol::Tilt,
+            "PLAYER" => Protocol::Player,
+            "SENSOR" => Protocol::Sensor,
+            "STROKE" => Protocol::Stroke,
+            "DIAL" => Protocol::Dial,
+            "MACRO" => Protocol::Macro,
             v => Protocol::Unknown(v.to_string())
         }
     }
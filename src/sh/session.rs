@@ -0,0 +1,101 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::clients::ClientId;
+use super::senders::Senders;
+use super::server::Server;
+
+#[test]
+fn should_round_trip_session_entries(){
+    let entry = SessionEntry{ time_us: 1500, client: 3, message: Some("MOUSE|1;2;0;0".to_string()) };
+    assert_eq!(entry.to_line(), "1500\t3\tMOUSE|1;2;0;0");
+    assert_eq!(SessionEntry::parse(&entry.to_line()), Some(entry));
+
+    let disconnect = SessionEntry{ time_us: 2000, client: 3, message: None };
+    assert_eq!(SessionEntry::parse(&disconnect.to_line()), Some(disconnect));
+
+    assert_eq!(SessionEntry::parse("garbage"), None);
+}
+
+/// One line of a session file: `time_us<TAB>client<TAB>PROTOCOL|data`
+///
+/// The time is in microseconds since the recording started. A line without a message
+/// marks the client disconnecting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionEntry {
+    pub time_us: u64,
+    pub client: ClientId,
+    pub message: Option<String>,
+}
+
+impl SessionEntry {
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}\t{}", self.time_us, self.client, self.message.as_deref().unwrap_or(""))
+    }
+
+    pub fn parse(line: &str) -> Option<SessionEntry>{
+        let mut split = line.splitn(3, '\t');
+        let time_us = split.next()?.parse::<u64>().ok()?;
+        let client = split.next()?.parse::<ClientId>().ok()?;
+        let message = split.next().filter(|message| !message.is_empty()).map(str::to_string);
+
+        Some(SessionEntry{ time_us, client, message })
+    }
+}
+
+/// Writes every message the server dispatches to a session file.
+#[derive(Clone)]
+pub struct SessionRecorder {
+    writer: Arc<Mutex<BufWriter<File>>>,
+    started: Instant,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self{ writer: Arc::new(Mutex::new(BufWriter::new(file))), started: Instant::now() })
+    }
+
+    /// Records a message from the client, `None` when it disconnected.
+    pub fn record(&self, client: ClientId, message: Option<&str>){
+        let entry = SessionEntry{
+            time_us: self.started.elapsed().as_micros() as u64,
+            client,
+            message: message.map(str::to_string),
+        };
+
+        // Flushed right away, a session is most useful when the server did not exit cleanly.
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writeln!(writer, "{}", entry.to_line());
+        let _ = writer.flush();
+    }
+}
+
+/// Feeds a recorded session through the senders with its original timing, `speed` above
+/// 1 plays it faster.
+pub fn replay(path: &Path, speed: f64, senders: &Senders) -> io::Result<()>{
+    let reader = BufReader::new(File::open(path)?);
+    let started = Instant::now();
+    let speed = speed.max(0.01);
+
+    for line in reader.lines() {
+        let entry = match SessionEntry::parse(&line?) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let due = Duration::from_secs_f64(entry.time_us as f64 / 1_000_000.0 / speed);
+        thread::sleep(due.saturating_sub(started.elapsed()));
+
+        match entry.message {
+            Some(message) => Server::sort_and_run_message(&message, entry.client, senders),
+            None => Server::disconnect(entry.client, senders),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::f32::consts::PI;
+
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_press_keys_of_stick_direction(){
+    let mut stick = StickMapper::new(StickSettings{ deadzone: 0.2, hysteresis: 0.0, ..StickSettings::default() });
+
+    stick.update("0;1");
+    assert_eq!(stick.keys(0.0), 1 << 32);
+    stick.update("-0.7;-0.7");
+    assert_eq!(stick.keys(0.0), 1 << 28 | 1 << 10);
+    stick.update("0.1;0.1");
+    assert_eq!(stick.keys(0.0), 0);
+
+    let mut stick = StickMapper::new(StickSettings{ directions: 4, hysteresis: 0.0, ..StickSettings::default() });
+    stick.update("0.7;0.6");
+    assert_eq!(stick.keys(0.0), 1 << 13);
+}
+
+#[test]
+fn should_hold_keys_inside_hysteresis(){
+    let mut stick = StickMapper::new(StickSettings{ deadzone: 0.2, hysteresis: 0.1, ..StickSettings::default() });
+
+    stick.update("0.25;0");
+    assert_eq!(stick.keys(0.0), 0);
+    stick.update("0.35;0");
+    assert_eq!(stick.keys(0.0), 1 << 13);
+    stick.update("0.25;0");
+    assert_eq!(stick.keys(0.0), 1 << 13);
+    stick.update("0.15;0");
+    assert_eq!(stick.keys(0.0), 0);
+}
+
+#[test]
+fn should_pulse_keys_for_partial_deflection(){
+    let mut stick = StickMapper::new(StickSettings{ deadzone: 0.0, hysteresis: 0.0, pulse_period: 0.1, full_deflection: 1.0, ..StickSettings::default() });
+
+    stick.update("0.5;0");
+    assert_eq!(stick.keys(0.02), 1 << 13);
+    assert_eq!(stick.keys(0.07), 0);
+    assert_eq!(stick.keys(0.12), 1 << 13);
+
+    stick.update("1;0");
+    assert_eq!(stick.keys(0.07), 1 << 13);
+}
+
+const SECTION: &str = "stick";
+
+// Bits of the up, down, left and right keys in the `KEYBOARD` key mask.
+const WASD: [u32; 4] = [32, 28, 10, 13];
+const ARROWS: [u32; 4] = [60, 61, 62, 63];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StickSettings {
+    /// Deflection in `0.0..=1.0` that presses no keys.
+    pub deadzone: f32,
+    /// Deflection past the deadzone needed to press the keys, they stay pressed until the
+    /// stick is back inside the deadzone. The current direction is widened by the same
+    /// fraction of its sector, so noise on a boundary doesn't make the keys chatter.
+    pub hysteresis: f32,
+    /// 4 presses a single key, 8 also presses two keys on the diagonals.
+    pub directions: u32,
+    /// Period in seconds of the key pulsing for partial deflection, 0 disables pulsing.
+    pub pulse_period: f32,
+    /// Deflection from which the keys are held instead of pulsed.
+    pub full_deflection: f32,
+    /// Bits of the up, down, left and right keys, `wasd` or `arrows` in the configuration.
+    pub keys: [u32; 4],
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        Self{ deadzone: 0.2, hysteresis: 0.05, directions: 8, pulse_period: 0.0, full_deflection: 0.9, keys: WASD }
+    }
+}
+
+impl StickSettings {
+    pub fn from_config(config: &ConfigFile) -> Self {
+        let default = Self::default();
+        let keys = match config.get(SECTION, "keys") {
+            Some("arrows") => ARROWS,
+            _ => WASD,
+        };
+
+        Self{
+            deadzone: config.get_or(SECTION, "deadzone", default.deadzone).clamp(0.0, 0.95),
+            hysteresis: config.get_or(SECTION, "hysteresis", default.hysteresis).max(0.0),
+            directions: if config.get_or(SECTION, "directions", default.directions) == 4 { 4 } else { 8 },
+            pulse_period: config.get_or(SECTION, "pulse_period", default.pulse_period).max(0.0),
+            full_deflection: config.get_or(SECTION, "full_deflection", default.full_deflection).clamp(0.0, 1.0),
+            keys,
+        }
+    }
+}
+
+/// Turns the stick position sent with the `STICK` protocol into keyboard key presses.
+///
+/// Messages are `x;y` in `-1.0..=1.0` with up being positive, the result is a key mask in
+/// the layout of the `KEYBOARD` protocol.
+#[derive(Debug)]
+pub struct StickMapper {
+    settings: StickSettings,
+    deflection: f32,
+    angle: f32,
+    sector: Option<i32>,
+}
+
+impl StickMapper {
+    pub fn new(settings: StickSettings) -> Self {
+        Self{ settings, deflection: 0.0, angle: 0.0, sector: None }
+    }
+
+    /// Handles a `STICK` message.
+    pub fn update(&mut self, data: &str){
+        let mut split = data.split(';');
+        let x = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(-1.0, 1.0);
+        let y = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32).clamp(-1.0, 1.0);
+
+        self.deflection = x.hypot(y).min(1.0);
+        self.angle = y.atan2(x);
+
+        let threshold = match self.sector {
+            Some(_) => self.settings.deadzone,
+            None => self.settings.deadzone + self.settings.hysteresis,
+        };
+
+        self.sector = if self.deflection > 0.0 && self.deflection >= threshold {
+            Some(self.sector_of_angle())
+        } else {
+            None
+        };
+    }
+
+    /// Returns the mask of the keys that are down at `time` seconds.
+    pub fn keys(&self, time: f64) -> u128 {
+        let sector = match self.sector {
+            Some(sector) => sector,
+            None => return 0,
+        };
+
+        if self.settings.pulse_period > 0.0 && self.deflection < self.settings.full_deflection {
+            let span = (self.settings.full_deflection - self.settings.deadzone).max(f32::EPSILON);
+            let duty = ((self.deflection - self.settings.deadzone) / span).clamp(0.0, 1.0) as f64;
+            let phase = time.rem_euclid(self.settings.pulse_period as f64) / self.settings.pulse_period as f64;
+            if phase >= duty {
+                return 0;
+            }
+        }
+
+        let direction = sector as f32 * self.sector_width();
+        let (horizontal, vertical) = (direction.cos().round() as i32, direction.sin().round() as i32);
+        let [up, down, left, right] = self.settings.keys;
+
+        let mut mask = 0u128;
+        match vertical {
+            1 => mask |= 1 << up,
+            -1 => mask |= 1 << down,
+            _ => {}
+        }
+        match horizontal {
+            1 => mask |= 1 << right,
+            -1 => mask |= 1 << left,
+            _ => {}
+        }
+        mask
+    }
+
+    fn sector_width(&self) -> f32 {
+        2.0 * PI / self.settings.directions as f32
+    }
+
+    fn sector_of_angle(&self) -> i32 {
+        let width = self.sector_width();
+        let count = self.settings.directions as i32;
+
+        // Stay in the current sector while the angle is within its widened bounds.
+        if let Some(sector) = self.sector {
+            let centre = sector as f32 * width;
+            let offset = (self.angle - centre + PI).rem_euclid(2.0 * PI) - PI;
+            if offset.abs() <= width * (0.5 + self.settings.hysteresis) {
+                return sector;
+            }
+        }
+
+        ((self.angle / width).round() as i32).rem_euclid(count)
+    }
+}
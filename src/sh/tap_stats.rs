@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+#[test]
+fn should_summarise_alternating_taps(){
+    let mut stats = TapStats::new(2);
+
+    // 1/4 notes at 150 BPM are 100 ms apart, each held for 40 ms.
+    for tap in 0..10 {
+        let time = tap as f64 * 0.1;
+        stats.record(tap % 2, true, time);
+        stats.record(tap % 2, false, time + 0.04);
+    }
+
+    let summary = stats.summary();
+    assert_eq!(summary.taps, 10);
+    assert!((summary.bpm - 150.0).abs() < 0.01);
+    assert!(summary.unstable_rate < 0.01);
+    assert!((summary.hold_ms - 40.0).abs() < 0.01);
+    assert_eq!(summary.key_taps, vec![5, 5]);
+    assert_eq!(summary.to_message(), "OSU_STATS|taps=10;bpm=150.0;ur=0.0;hold=40.0;keys=5/5");
+}
+
+#[test]
+fn should_forget_taps_outside_window(){
+    let mut stats = TapStats::new(2);
+
+    stats.record(0, true, 0.0);
+    stats.record(0, false, 0.1);
+    stats.record(1, true, WINDOW + 1.0);
+
+    assert_eq!(stats.summary().key_taps, vec![0, 1]);
+}
+
+// Length of the sliding window in seconds, ending at the latest key change.
+const WINDOW: f64 = 10.0;
+
+/// Tap timing of one osu! client over a sliding window.
+#[derive(Debug)]
+pub struct TapStats {
+    presses: VecDeque<(usize, f64)>,
+    holds: VecDeque<(f64, f64)>,
+    pressed_at: Vec<Option<f64>>,
+}
+
+impl TapStats {
+    pub fn new(key_count: usize) -> Self {
+        Self{ presses: VecDeque::new(), holds: VecDeque::new(), pressed_at: vec![None; key_count] }
+    }
+
+    /// Records a press or release of the key at `time` seconds.
+    pub fn record(&mut self, key: usize, pressed: bool, time: f64){
+        if key >= self.pressed_at.len() {
+            return;
+        }
+
+        if pressed {
+            self.presses.push_back((key, time));
+            self.pressed_at[key] = Some(time);
+        } else if let Some(pressed_at) = self.pressed_at[key].take() {
+            self.holds.push_back((time, time - pressed_at));
+        }
+
+        while self.presses.front().map(|(_, pressed)| time - pressed > WINDOW).unwrap_or(false) {
+            self.presses.pop_front();
+        }
+        while self.holds.front().map(|(released, _)| time - released > WINDOW).unwrap_or(false) {
+            self.holds.pop_front();
+        }
+    }
+
+    pub fn summary(&self) -> TapSummary {
+        let times: Vec<f64> = self.presses.iter().map(|(_, time)| *time).collect();
+        let intervals: Vec<f64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        // Tapping speed is given in BPM of 1/4 notes, like osu! tapping tests do.
+        let bpm = match (times.first(), times.last()) {
+            (Some(first), Some(last)) if last > first => intervals.len() as f64 / (last - first) * 60.0 / 4.0,
+            _ => 0.0,
+        };
+
+        // Without a beatmap the hit errors are unknown, so the unstable rate is taken from
+        // the spread of the intervals: 10 times their standard deviation in milliseconds.
+        let unstable_rate = match intervals.len() {
+            0 => 0.0,
+            count => {
+                let mean = intervals.iter().sum::<f64>() / count as f64;
+                let variance = intervals.iter().map(|interval| (interval - mean).powi(2)).sum::<f64>() / count as f64;
+                variance.sqrt() * 1000.0 * 10.0
+            }
+        };
+
+        let hold_ms = match self.holds.len() {
+            0 => 0.0,
+            count => self.holds.iter().map(|(_, duration)| duration).sum::<f64>() / count as f64 * 1000.0,
+        };
+
+        let mut key_taps = vec![0; self.pressed_at.len()];
+        for (key, _) in self.presses.iter() {
+            key_taps[*key] += 1;
+        }
+
+        TapSummary{ taps: self.presses.len(), bpm, unstable_rate, hold_ms, key_taps }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapSummary {
+    pub taps: usize,
+    pub bpm: f64,
+    pub unstable_rate: f64,
+    /// Average time a key was held down.
+    pub hold_ms: f64,
+    /// Taps of every key, shows the K1/K2 balance.
+    pub key_taps: Vec<usize>,
+}
+
+impl TapSummary {
+    /// Formats the summary as sent to the client: `OSU_STATS|taps=..;bpm=..;ur=..;hold=..;keys=..`
+    pub fn to_message(&self) -> String {
+        format!("OSU_STATS|{}", self)
+    }
+}
+
+impl fmt::Display for TapSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.key_taps.iter().map(|taps| taps.to_string()).collect();
+        write!(f, "taps={};bpm={:.1};ur={:.1};hold={:.1};keys={}", self.taps, self.bpm, self.unstable_rate, self.hold_ms, keys.join("/"))
+    }
+}
@@ -0,0 +1,109 @@
+use super::config_file::ConfigFile;
+use super::gamepad_data::GamepadAxis;
+
+#[test]
+fn should_map_tilt_relative_to_centre(){
+    let mut tilt = TiltMapper::new(TiltSettings{ range: 40.0, deadzone: 0.0, curve: 1.0, ..TiltSettings::default() });
+
+    tilt.update("10;5");
+    tilt.update("calibrate");
+    assert_eq!(tilt.update("30;5"), (0.5, 0.0));
+    assert_eq!(tilt.update("-70;5"), (-1.0, 0.0));
+}
+
+#[test]
+fn should_apply_deadzone_and_curve(){
+    let mut tilt = TiltMapper::new(TiltSettings{ range: 22.0, deadzone: 2.0, curve: 2.0, ..TiltSettings::default() });
+
+    assert_eq!(tilt.update("1.5;0").0, 0.0);
+    assert_eq!(tilt.update("12;0").0, 0.25);
+    assert_eq!(tilt.update("-22;0").0, -1.0);
+}
+
+const SECTION: &str = "tilt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiltSettings {
+    /// Gamepad axis driven by rolling the phone like a steering wheel.
+    pub roll_axis: Option<GamepadAxis>,
+    /// Gamepad axis driven by tilting the top of the phone towards or away from the user.
+    pub pitch_axis: Option<GamepadAxis>,
+    /// Angle in degrees, measured from the centre, that gives full deflection.
+    pub range: f32,
+    /// Angle in degrees around the centre that is treated as no input.
+    pub deadzone: f32,
+    /// Exponent of the response curve, values above 1 give finer control near the centre.
+    pub curve: f32,
+}
+
+impl Default for TiltSettings {
+    fn default() -> Self {
+        Self{ roll_axis: Some(GamepadAxis::LeftX), pitch_axis: None, range: 45.0, deadzone: 2.0, curve: 1.0 }
+    }
+}
+
+impl TiltSettings {
+    pub fn from_config(config: &ConfigFile) -> Self {
+        let default = Self::default();
+        let axis = |key: &str, default: Option<GamepadAxis>| match config.get(SECTION, key) {
+            Some(name) => GamepadAxis::from_name(name),
+            None => default,
+        };
+
+        Self{
+            roll_axis: axis("roll_axis", default.roll_axis),
+            pitch_axis: axis("pitch_axis", default.pitch_axis),
+            range: config.get_or(SECTION, "range", default.range).max(1.0),
+            deadzone: config.get_or(SECTION, "deadzone", default.deadzone).max(0.0),
+            curve: config.get_or(SECTION, "curve", default.curve).max(0.1),
+        }
+    }
+}
+
+/// Turns the phone orientation sent with the `TILT` protocol into gamepad axis values.
+///
+/// Messages are either `roll;pitch` in degrees or `calibrate`, which makes the last
+/// received orientation the new centre.
+#[derive(Debug)]
+pub struct TiltMapper {
+    settings: TiltSettings,
+    centre: (f32, f32),
+    last: (f32, f32),
+}
+
+impl TiltMapper {
+    pub fn new(settings: TiltSettings) -> Self {
+        Self{ settings, centre: (0.0, 0.0), last: (0.0, 0.0) }
+    }
+
+    pub fn settings(&self) -> &TiltSettings {
+        &self.settings
+    }
+
+    /// Handles a `TILT` message and returns the roll and pitch axis values.
+    pub fn update(&mut self, data: &str) -> (f32, f32){
+        if data.trim() == "calibrate" {
+            self.centre = self.last;
+        } else {
+            let mut split = data.split(';');
+            let roll = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32);
+            let pitch = split.next().unwrap_or("0").parse::<f32>().unwrap_or(0f32);
+            self.last = (roll, pitch);
+        }
+
+        (self.map(self.last.0 - self.centre.0), self.map(self.last.1 - self.centre.1))
+    }
+
+    fn map(&self, angle: f32) -> f32 {
+        // Keep the difference in -180..180 so a centre near the wrap-around still works.
+        let angle = (angle + 180.0).rem_euclid(360.0) - 180.0;
+
+        if angle.abs() <= self.settings.deadzone {
+            return 0.0;
+        }
+
+        let span = (self.settings.range - self.settings.deadzone).max(f32::EPSILON);
+        let deflection = ((angle.abs() - self.settings.deadzone) / span).min(1.0);
+        deflection.powf(self.settings.curve) * angle.signum()
+    }
+}
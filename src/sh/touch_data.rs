@@ -0,0 +1,123 @@
+#[test]
+fn should_parse_button_state_and_contacts(){
+    let data = TouchData::new("1;3,0.25,0.5;7,1,0".to_string());
+
+    assert_eq!(data.button_state, 1);
+    assert_eq!(data.contacts.len(), 2);
+    assert_eq!(data.contacts[0].id, 3);
+    assert_eq!(data.contacts[1].x, 1.0);
+}
+
+#[test]
+fn should_keep_contacts_in_their_slots(){
+    let mut slots = TouchSlots::new(5);
+
+    let events = slots.update(&TouchData::new("0;1,0.1,0.1;2,0.9,0.9".to_string()).contacts);
+    assert!(matches!(events[..], [SlotEvent::Down{ slot: 0, .. }, SlotEvent::Down{ slot: 1, .. }]));
+
+    let events = slots.update(&TouchData::new("0;2,0.8,0.8".to_string()).contacts);
+    assert!(matches!(events[..], [SlotEvent::Up{ slot: 0 }, SlotEvent::Move{ slot: 1, .. }]));
+    assert_eq!(slots.active(), 1);
+
+    let events = slots.update(&TouchData::new("0;2,0.8,0.8;3,0.5,0.5".to_string()).contacts);
+    assert!(matches!(events[..], [SlotEvent::Down{ slot: 0, tracking_id: 2, .. }]));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchContact {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A single frame of the `TOUCHPAD` protocol: `button_state;id,x,y;id,x,y;...`
+///
+/// Every frame lists all fingers that currently touch the pad, positions are
+/// normalized to `0.0..=1.0`. A contact that is missing from a frame was lifted.
+pub struct TouchData {
+    pub button_state: u8,
+    pub contacts: Vec<TouchContact>,
+}
+
+impl TouchData {
+    pub fn new(data_string: String) -> Self {
+        let mut split = data_string.split(';');
+        let buttons = split.next().unwrap_or("0").parse::<u8>().unwrap_or(0u8);
+
+        let contacts = split.filter_map(|contact| {
+            let mut values = contact.split(',');
+            let id = values.next()?.parse::<u32>().ok()?;
+            let x = values.next()?.parse::<f32>().ok()?.clamp(0.0, 1.0);
+            let y = values.next()?.parse::<f32>().ok()?.clamp(0.0, 1.0);
+            Some(TouchContact{ id, x, y })
+        }).collect();
+
+        Self{ button_state: buttons, contacts }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlotEvent {
+    Down{ slot: usize, tracking_id: i32, x: f32, y: f32 },
+    Move{ slot: usize, x: f32, y: f32 },
+    Up{ slot: usize },
+}
+
+/// Assigns client contacts to multitouch slots and reports what changed between frames.
+#[derive(Debug)]
+pub struct TouchSlots {
+    slots: Vec<Option<TouchContact>>,
+    next_tracking_id: i32,
+}
+
+impl TouchSlots {
+    pub fn new(count: usize) -> Self {
+        Self{ slots: vec![None; count], next_tracking_id: 0 }
+    }
+
+    /// Number of contacts currently on the pad.
+    pub fn active(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// Position of the contact in the lowest slot, used for single touch emulation.
+    pub fn primary(&self) -> Option<(f32, f32)> {
+        self.slots.iter().flatten().next().map(|contact| (contact.x, contact.y))
+    }
+
+    pub fn update(&mut self, contacts: &[TouchContact]) -> Vec<SlotEvent> {
+        let mut events = vec![];
+
+        for (slot, current) in self.slots.iter_mut().enumerate() {
+            if let Some(contact) = current {
+                match contacts.iter().find(|new| new.id == contact.id) {
+                    Some(new) => {
+                        if new.x != contact.x || new.y != contact.y {
+                            events.push(SlotEvent::Move{ slot, x: new.x, y: new.y });
+                        }
+                        *current = Some(*new);
+                    }
+                    None => {
+                        events.push(SlotEvent::Up{ slot });
+                        *current = None;
+                    }
+                }
+            }
+        }
+
+        for contact in contacts {
+            if self.slots.iter().flatten().any(|existing| existing.id == contact.id) {
+                continue;
+            }
+
+            if let Some(slot) = self.slots.iter().position(|slot| slot.is_none()) {
+                let tracking_id = self.next_tracking_id;
+                self.next_tracking_id = (self.next_tracking_id + 1) % i32::MAX;
+                self.slots[slot] = Some(*contact);
+                events.push(SlotEvent::Down{ slot, tracking_id, x: contact.x, y: contact.y });
+            }
+        }
+
+        events
+    }
+}
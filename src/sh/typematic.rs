@@ -0,0 +1,191 @@
+use super::config_file::ConfigFile;
+
+#[test]
+fn should_repeat_last_key_while_fresh(){
+    let settings = RepeatSettings{ mode: RepeatMode::Server, delay: 0.5, period: 0.1, staleness: 0.3 };
+    let mut typematic = Typematic::new(settings);
+
+    typematic.key_event("a", 1, 0.0);
+    assert_eq!(typematic.tick(0.4, 0.3), vec![]);
+    assert_eq!(typematic.tick(0.5, 0.4), vec![("a", 0), ("a", 1)]);
+    assert_eq!(typematic.next_due(), Some(0.6));
+
+    typematic.key_event("b", 1, 0.8);
+    typematic.key_event("a", 0, 0.8);
+    assert_eq!(typematic.tick(1.3, 1.3), vec![("b", 0), ("b", 1)]);
+    typematic.key_event("b", 0, 1.35);
+    assert_eq!(typematic.next_due(), None);
+}
+
+#[test]
+fn should_release_held_keys_while_stale(){
+    let settings = RepeatSettings{ mode: RepeatMode::Server, delay: 0.5, period: 0.1, staleness: 0.3 };
+    let mut typematic = Typematic::new(settings);
+
+    typematic.key_event("ctrl", 1, 0.0);
+    typematic.key_event("a", 1, 0.0);
+    // The client state is too old, the release may be on its way.
+    assert_eq!(typematic.tick(0.4, 0.05), vec![("a", 0), ("ctrl", 0)]);
+    assert_eq!(typematic.next_due(), None);
+    assert_eq!(typematic.tick(0.5, 0.05), vec![]);
+
+    // A fresh state that still holds them presses them again, repeating after the delay.
+    assert_eq!(typematic.tick(0.6, 0.55), vec![("ctrl", 1), ("a", 1)]);
+    assert_eq!(typematic.tick(1.0, 0.95), vec![]);
+    assert_eq!(typematic.tick(1.1, 1.05), vec![("a", 0), ("a", 1)]);
+
+    // A release that arrives late leaves nothing to press again.
+    assert_eq!(typematic.tick(1.5, 1.1), vec![("a", 0), ("ctrl", 0)]);
+    typematic.key_event("a", 0, 1.6);
+    typematic.key_event("ctrl", 0, 1.6);
+    assert_eq!(typematic.tick(1.6, 1.6), vec![]);
+}
+
+const SECTION: &str = "keyboard";
+
+/// Who repeats held keys, `repeat` in the `[keyboard]` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// The device declares no repeat, the compositor repeats keys as it does for any keyboard.
+    System,
+    /// The device declares `EV_REP`, so the kernel repeats keys.
+    Kernel,
+    /// Keys are repeated here while the client state is fresh, and released while it is stale.
+    Server,
+}
+
+/// How held keys repeat, from the `[keyboard]` section.
+///
+/// With `repeat = server` repeating is done here, and only while the client state is
+/// newer than `repeat_staleness`. Once it is older the held keys are released, so a late
+/// release packet can not leave a key repeating, and they are pressed again when a fresh
+/// state still holds them.
+///
+/// Compositors ignore repeat events and repeat held keys on their own, so a repeat is a
+/// release and a new press. That also restarts the compositor's own repeat delay, which
+/// never runs out as long as `delay` is shorter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepeatSettings {
+    pub mode: RepeatMode,
+    /// Time until a held key starts repeating, in seconds.
+    pub delay: f64,
+    /// Time between repeats, in seconds.
+    pub period: f64,
+    /// Age of the client state after which repeats stop, in seconds.
+    pub staleness: f64,
+}
+
+impl Default for RepeatSettings {
+    fn default() -> Self {
+        Self{ mode: RepeatMode::System, delay: 0.4, period: 1.0 / 30.0, staleness: 0.3 }
+    }
+}
+
+impl RepeatSettings {
+    /// Reads `repeat`, `repeat_delay` and `repeat_staleness` in milliseconds and
+    /// `repeat_rate` in repeats per second.
+    pub fn from_config(config: &ConfigFile) -> Self {
+        let default = RepeatSettings::default();
+
+        let mode = match config.get(SECTION, "repeat").unwrap_or("system") {
+            "system" => RepeatMode::System,
+            "kernel" => RepeatMode::Kernel,
+            "server" => RepeatMode::Server,
+            other => {
+                println!("Unknown keyboard repeat: {}, the system repeats keys.", other);
+                RepeatMode::System
+            }
+        };
+
+        Self{
+            mode,
+            delay: config.get_or(SECTION, "repeat_delay", default.delay * 1000.0).max(0.0) / 1000.0,
+            period: 1.0 / config.get_or(SECTION, "repeat_rate", 1.0 / default.period).clamp(1.0, 100.0),
+            staleness: config.get_or(SECTION, "repeat_staleness", default.staleness * 1000.0).max(0.0) / 1000.0,
+        }
+    }
+}
+
+/// Repeats the last pressed key, like a hardware keyboard does, and takes held keys back
+/// while the client state is stale.
+///
+/// Time is passed in seconds.
+#[derive(Debug)]
+pub struct Typematic<K> {
+    settings: RepeatSettings,
+    // Keys held on the device, in the order they were pressed.
+    held: Vec<K>,
+    // Held keys released because the client state went stale.
+    suspended: Vec<K>,
+    // The key to repeat and when it repeats next.
+    repeating: Option<(K, f64)>,
+}
+
+impl<K: Copy + PartialEq> Typematic<K> {
+    pub fn new(settings: RepeatSettings) -> Self {
+        Self{ settings, held: vec![], suspended: vec![], repeating: None }
+    }
+
+    /// Follows the key events sent to the device.
+    pub fn key_event(&mut self, key: K, state: i32, time: f64){
+        if self.settings.mode != RepeatMode::Server {
+            return;
+        }
+
+        self.held.retain(|held| *held != key);
+        self.suspended.retain(|suspended| *suspended != key);
+        match state {
+            1 => {
+                self.held.push(key);
+                self.repeating = Some((key, time + self.settings.delay));
+            }
+            0 if self.repeating.map(|(repeating, _)| repeating) == Some(key) => self.repeating = None,
+            _ => {}
+        }
+    }
+
+    /// Returns the key events to send at `time`, `updated` is when the client state last
+    /// arrived. These are repeats, or the held keys going up while the state is stale and
+    /// down again once it is fresh. Each event needs a frame of its own, a release and
+    /// press in one frame may be merged.
+    pub fn tick(&mut self, time: f64, updated: f64) -> Vec<(K, i32)>{
+        if time - updated > self.settings.staleness {
+            // Released in reverse, so modifiers go up last.
+            let released: Vec<K> = self.held.iter().rev().filter(|key| !self.suspended.contains(key)).copied().collect();
+            self.suspended.extend(released.iter().copied());
+            return released.into_iter().map(|key| (key, 0)).collect();
+        }
+
+        if !self.suspended.is_empty() {
+            let pressed: Vec<(K, i32)> = self.held.iter().filter(|key| self.suspended.contains(key)).map(|key| (*key, 1)).collect();
+            self.suspended.clear();
+            if let Some((_, next)) = self.repeating.as_mut() {
+                *next = time + self.settings.delay;
+            }
+            return pressed;
+        }
+
+        let (key, next) = match self.repeating.as_mut() {
+            Some(repeating) => repeating,
+            None => return vec![],
+        };
+        if time < *next {
+            return vec![];
+        }
+
+        // Repeats missed while busy are skipped rather than sent in a burst.
+        *next += self.settings.period;
+        if *next <= time {
+            *next = time + self.settings.period;
+        }
+        vec![(*key, 0), (*key, 1)]
+    }
+
+    /// Time the next repeat is due, `None` when no key repeats.
+    pub fn next_due(&self) -> Option<f64>{
+        match self.suspended.is_empty() {
+            true => self.repeating.map(|(_, next)| next),
+            false => None,
+        }
+    }
+}
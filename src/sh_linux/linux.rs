@@ -1,3 +1,11 @@
 pub mod keyboard_input;
 pub mod osu_input;
 pub mod mouse_input;
+pub mod touchpad_input;
+pub mod pen_input;
+pub mod gamepad_input;
+pub mod virtual_keyboard;
+pub mod dial_input;
+pub mod key_remap_config;
+pub mod bitmask_input;
+pub mod sighup;
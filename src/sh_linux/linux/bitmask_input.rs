@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use evdev::{AttributeSet, EventType, InputEvent, Key};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+
+use crate::sh::bitmask_device::{BitmaskDevice, BitmaskMessage};
+use crate::sh::clients::ClientId;
+use crate::sh::config_file::SharedConfig;
+use crate::sh::config_watcher::ReloadTracker;
+use crate::sh::key_input::{DebounceSettings, GlitchReport, LayoutInput};
+use crate::sh::key_remap::KeyRemap;
+use crate::sh_linux::linux::key_remap_config::load_key_remap;
+
+/// Runs a device defined in the config, every client presses the keys of its own mask.
+/// Debouncing is read from the `[debounce <protocol>]` section, in lower case.
+///
+/// The device is removed, with its keys released, once its sender is dropped.
+pub fn start_bitmask_input(receiver: mpsc::Receiver<BitmaskMessage>, device: BitmaskDevice<Key>, config: SharedConfig){
+
+    let layout = &device.keys;
+    let load = |config: &SharedConfig| {
+        let config = config.lock().unwrap();
+        (load_key_remap(&config), DebounceSettings::from_config(&config, &device.protocol.to_lowercase()))
+    };
+    let (mut remap, mut debounce) = load(&config);
+    let mut reloads = ReloadTracker::new(&config);
+    let mut virtual_device = create_device(&device.name, layout, &remap);
+
+    // Keys of every client, pressed for the latest mask it sent.
+    let mut clients: HashMap<ClientId, LayoutInput<Key>> = HashMap::new();
+    let mut glitch_report = GlitchReport::new(&device.protocol);
+    let started = Instant::now();
+
+    loop{
+        let due = clients.values().filter_map(|keys| keys.next_due()).reduce(f64::min);
+        let message = match due {
+            Some(due) => receiver.recv_timeout(Duration::from_secs_f64((due - started.elapsed().as_secs_f64()).max(0.0))),
+            None => receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+
+        let time = started.elapsed().as_secs_f64();
+        let mut key_events: Vec<(Key, i32)> = vec![];
+
+        match message {
+            Ok(BitmaskMessage::Data(client, data)) => match device.parse_mask(&data) {
+                Some(mask) => clients.entry(client).or_insert_with(|| LayoutInput::new(layout, debounce)).set_mask(mask),
+                None => println!("Invalid mask for {}: {}", device.protocol, data),
+            },
+            Ok(BitmaskMessage::Disconnected(client)) => {
+                if let Some(mut keys) = clients.remove(&client) {
+                    key_events.extend(released(&mut keys));
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                key_events.extend(clients.values_mut().flat_map(released));
+                emit(&mut virtual_device, &mut remap, key_events);
+                return;
+            }
+        }
+
+        // Keys are released with the old settings and pressed again below with the new ones.
+        if reloads.reloaded(&config) {
+            key_events.extend(clients.values_mut().flat_map(released));
+            emit(&mut virtual_device, &mut remap, std::mem::take(&mut key_events));
+
+            let old_targets = remap.targets();
+            (remap, debounce) = load(&config);
+            if remap.targets() != old_targets {
+                virtual_device = create_device(&device.name, layout, &remap);
+            }
+            for keys in clients.values_mut() {
+                *keys = LayoutInput::new(layout, debounce).with_mask(keys.mask());
+            }
+        }
+
+        // Debounced changes that were held back are sent once due.
+        for keys in clients.values_mut() {
+            key_events.extend(keys.get_events_at(time).into_iter().map(|(_, key, state)| (key, state)));
+            glitch_report.add(keys.take_glitches(), time);
+        }
+
+        emit(&mut virtual_device, &mut remap, key_events);
+    }
+}
+
+/// Releases all keys of a client without debouncing.
+fn released(keys: &mut LayoutInput<Key>) -> Vec<(Key, i32)>{
+    keys.release().into_iter().map(|(_, key, state)| (key, state)).collect()
+}
+
+fn create_device(name: &str, layout: &[Key], remap: &KeyRemap<Key>) -> VirtualDevice {
+    let mut keys = AttributeSet::<Key>::new();
+    for key in layout.iter().chain(remap.targets().iter()) {
+        keys.insert(*key);
+    }
+
+    VirtualDeviceBuilder::new().expect("Failed to create virtual device.")
+        .name(name)
+        .with_keys(&keys).expect("Failed to init keys for the device.")
+        .build().unwrap()
+}
+
+fn emit(device: &mut VirtualDevice, remap: &mut KeyRemap<Key>, key_events: Vec<(Key, i32)>){
+    let events: Vec<InputEvent> = key_events.into_iter()
+        .filter_map(|(key, state)| remap.remap(key, state))
+        .map(|(key, state)| InputEvent::new(EventType::KEY, key.code(), state))
+        .collect();
+
+    if !events.is_empty() {
+        device.emit(&events).unwrap();
+    }
+}
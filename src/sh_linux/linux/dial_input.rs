@@ -0,0 +1,49 @@
+use std::sync::mpsc;
+
+use evdev::{AttributeSet, EventType, InputEvent, Key, RelativeAxisType};
+use evdev::uinput::VirtualDeviceBuilder;
+
+use crate::sh::dial_data::DialData;
+use crate::sh::key_input::KeyInputU8;
+
+/// A rotary dial like the Surface Dial: a relative `REL_DIAL` wheel that can be pressed.
+pub fn start_dial_input(receiver: mpsc::Receiver<String>){
+
+    let mut keys = AttributeSet::<Key>::new();
+    keys.insert(Key::BTN_0);
+
+    let mut axes = AttributeSet::<RelativeAxisType>::new();
+    axes.insert(RelativeAxisType::REL_DIAL);
+    axes.insert(RelativeAxisType::REL_MISC);
+
+    let mut device = VirtualDeviceBuilder::new().expect("Failed to create virtual device: dial")
+        .name("Virtual dial input")
+        .with_keys(&keys).expect("Failed to create button for dial input.")
+        .with_relative_axes(&axes).expect("Failed to create relative axes for dial input.")
+        .build().unwrap();
+
+    let mut button = KeyInputU8::new(0, Key::BTN_0);
+
+    // Ends once the sender is dropped.
+    while let Ok(message) = receiver.recv(){
+        let dial_state = DialData::new(message);
+
+        let mut events: Vec<InputEvent> = vec![];
+
+        if dial_state.rotation != 0 {
+            events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_DIAL.0, dial_state.rotation));
+        }
+
+        if dial_state.misc != 0 {
+            events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_MISC.0, dial_state.misc));
+        }
+
+        if let Some((key, state)) = button.get_event(dial_state.button_state){
+            events.push(InputEvent::new(EventType::KEY, key.code(), state));
+        }
+
+        if !events.is_empty() {
+            let _ = device.emit(&events);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, FFEffectKind, FFEffectType, InputEvent, InputEventKind, InputId, Key, UInputEventType, UinputAbsSetup};
+use evdev::uinput::{UInputEvent, VirtualDevice, VirtualDeviceBuilder};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+
+use crate::sh::clients::{ClientId, Clients};
+
+use crate::sh::config_file::SharedConfig;
+use crate::sh::config_watcher::ReloadTracker;
+use crate::sh::gamepad_data::{GamepadData, GamepadMessage};
+use crate::sh::key_input::KeyInputU128;
+use crate::sh::player_slots::PlayerSlots;
+use crate::sh::rumble::{Rumble, RumbleEffects};
+use crate::sh::tilt_mapper::{TiltMapper, TiltSettings};
+
+// Identify as an Xbox 360 controller so SDL and Steam pick the right mapping.
+// The version is offset by the player number to keep the IDs of the players apart.
+const VENDOR: u16 = 0x045e;
+const PRODUCT: u16 = 0x028e;
+const VERSION: u16 = 0x0110;
+
+const STICK_MAX: f32 = 32767.0;
+const TRIGGER_MAX: f32 = 255.0;
+
+const FF_EFFECTS_MAX: u32 = 16;
+// How often force feedback requests from games are checked while no input arrives.
+const FF_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Hands out player slots and routes each client's input to the gamepad of its slot.
+///
+/// A gamepad device is created when its slot is first used and removed again once the
+/// slot is released, so games only see the controllers that are actually in use.
+pub fn start_gamepad_input(receiver: mpsc::Receiver<GamepadMessage>, config: SharedConfig, clients: Clients){
+    let mut slots = PlayerSlots::default();
+    let mut players: HashMap<usize, mpsc::Sender<GamepadMessage>> = HashMap::new();
+
+    for message in receiver.iter() {
+        match message {
+            GamepadMessage::Claim(client, data) => {
+                let previous = slots.player_of(client);
+                let requested = data.trim().parse::<usize>().ok();
+
+                match slots.claim(client, requested) {
+                    Some(player) => {
+                        if let Some(previous) = previous.filter(|previous| *previous != player) {
+                            players.remove(&previous);
+                        }
+                        clients.send(client, &format!("PLAYER|{}", player));
+                    }
+                    None => clients.send(client, "PLAYER|0"),
+                }
+            }
+            GamepadMessage::Disconnected(client) => {
+                // Dropping the sender ends the gamepad thread, which removes the device.
+                if let Some(player) = slots.release(client) {
+                    players.remove(&player);
+                }
+            }
+            GamepadMessage::Data(client, _) | GamepadMessage::Tilt(client, _) => {
+                let assigned = slots.player_of(client).is_some();
+                let player = match slots.claim(client, None) {
+                    Some(player) => player,
+                    None => continue,
+                };
+
+                // A client that sends input without claiming a slot is told the one it got,
+                // a claimed slot was already answered.
+                if !assigned {
+                    clients.send(client, &format!("PLAYER|{}", player));
+                }
+
+                let sender = players.entry(player).or_insert_with(|| {
+                    let (sender, receiver) = mpsc::channel();
+                    let (config, clients) = (config.clone(), clients.clone());
+                    thread::spawn(move || start_player_gamepad(player, receiver, config, clients));
+                    sender
+                });
+                let _ = sender.send(message);
+            }
+        }
+    }
+}
+
+fn start_player_gamepad(player: usize, receiver: mpsc::Receiver<GamepadMessage>, config: SharedConfig, clients: Clients){
+
+    let mut tilt = TiltMapper::new(TiltSettings::from_config(&config.lock().unwrap()));
+    let mut reloads = ReloadTracker::new(&config);
+
+    let mut keys = AttributeSet::<Key>::new();
+    keys.insert(Key::BTN_SOUTH);
+    keys.insert(Key::BTN_EAST);
+    keys.insert(Key::BTN_NORTH);
+    keys.insert(Key::BTN_WEST);
+    keys.insert(Key::BTN_TL);
+    keys.insert(Key::BTN_TR);
+    keys.insert(Key::BTN_SELECT);
+    keys.insert(Key::BTN_START);
+    keys.insert(Key::BTN_MODE);
+    keys.insert(Key::BTN_THUMBL);
+    keys.insert(Key::BTN_THUMBR);
+
+    let stick = AbsInfo::new(0, -32768, 32767, 16, 128, 0);
+    let trigger = AbsInfo::new(0, 0, 255, 0, 0, 0);
+    let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+
+    let mut force_feedback = AttributeSet::<FFEffectType>::new();
+    force_feedback.insert(FFEffectType::FF_RUMBLE);
+    force_feedback.insert(FFEffectType::FF_GAIN);
+
+    let mut device = VirtualDeviceBuilder::new().expect("Failed to create virtual device: gamepad")
+        .name(&format!("Virtual gamepad input (player {})", player))
+        .input_id(InputId::new(BusType::BUS_USB, VENDOR, PRODUCT, VERSION + player as u16 - 1))
+        .with_keys(&keys).expect("Failed to create buttons for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, stick)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, stick)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RX, stick)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RY, stick)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Z, trigger)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RZ, trigger)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0X, hat)).expect("Failed to create axes for gamepad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0Y, hat)).expect("Failed to create axes for gamepad input.")
+        .with_ff(&force_feedback).expect("Failed to create force feedback for gamepad input.")
+        .with_ff_effects_max(FF_EFFECTS_MAX)
+        .build().unwrap();
+
+    // Force feedback requests are read in between client messages, so reading must not block.
+    fcntl(device.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).expect("Failed to set up force feedback for gamepad input.");
+    let mut effects = RumbleEffects::default();
+    let mut owner: Option<ClientId> = None;
+
+    let mut buttons = [
+        KeyInputU128::new(0, Key::BTN_SOUTH),
+        KeyInputU128::new(1, Key::BTN_EAST),
+        KeyInputU128::new(2, Key::BTN_NORTH),
+        KeyInputU128::new(3, Key::BTN_WEST),
+        KeyInputU128::new(4, Key::BTN_TL),
+        KeyInputU128::new(5, Key::BTN_TR),
+        KeyInputU128::new(6, Key::BTN_SELECT),
+        KeyInputU128::new(7, Key::BTN_START),
+        KeyInputU128::new(8, Key::BTN_MODE),
+        KeyInputU128::new(9, Key::BTN_THUMBL),
+        KeyInputU128::new(10, Key::BTN_THUMBR),
+    ];
+
+    let mut axes = [
+        (AbsoluteAxisType::ABS_X, 0),
+        (AbsoluteAxisType::ABS_Y, 0),
+        (AbsoluteAxisType::ABS_RX, 0),
+        (AbsoluteAxisType::ABS_RY, 0),
+        (AbsoluteAxisType::ABS_Z, 0),
+        (AbsoluteAxisType::ABS_RZ, 0),
+        (AbsoluteAxisType::ABS_HAT0X, 0),
+        (AbsoluteAxisType::ABS_HAT0Y, 0),
+    ];
+
+    let mut client_state = GamepadData::default();
+    let mut tilt_state = (0.0, 0.0);
+
+    loop{
+        for rumble in read_force_feedback(&mut device, &mut effects) {
+            if let Some(owner) = owner {
+                clients.send(owner, &rumble.to_message());
+            }
+        }
+
+        if reloads.reloaded(&config) {
+            tilt = TiltMapper::new(TiltSettings::from_config(&config.lock().unwrap()));
+            tilt_state = (0.0, 0.0);
+        }
+
+        // Rumble goes back to the client that last sent input to this gamepad.
+        match receiver.recv_timeout(FF_POLL_INTERVAL) {
+            Ok(GamepadMessage::Data(client, message)) => {
+                owner = Some(client);
+                client_state = GamepadData::new(message);
+            }
+            Ok(GamepadMessage::Tilt(client, message)) => {
+                owner = Some(client);
+                tilt_state = tilt.update(&message);
+            }
+            Ok(_) => continue,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        let mut gamepad_state = client_state.clone();
+        if let Some(axis) = tilt.settings().roll_axis {
+            gamepad_state.add_to_axis(axis, tilt_state.0);
+        }
+        if let Some(axis) = tilt.settings().pitch_axis {
+            gamepad_state.add_to_axis(axis, tilt_state.1);
+        }
+
+        // Linux reports up as negative on the Y axes.
+        let values = [
+            (gamepad_state.left_x * STICK_MAX) as i32,
+            (-gamepad_state.left_y * STICK_MAX) as i32,
+            (gamepad_state.right_x * STICK_MAX) as i32,
+            (-gamepad_state.right_y * STICK_MAX) as i32,
+            (gamepad_state.left_trigger * TRIGGER_MAX) as i32,
+            (gamepad_state.right_trigger * TRIGGER_MAX) as i32,
+            gamepad_state.hat_x,
+            -gamepad_state.hat_y,
+        ];
+
+        let mut events: Vec<InputEvent> = vec![];
+
+        for ((axis, current), value) in axes.iter_mut().zip(values) {
+            if *current != value {
+                *current = value;
+                events.push(InputEvent::new(EventType::ABSOLUTE, axis.0, value));
+            }
+        }
+
+        for button in buttons.iter_mut() {
+            if let Some((key, state)) = button.get_event(gamepad_state.button_state as u128){
+                events.push(InputEvent::new(EventType::KEY, key.code(), state));
+            }
+        }
+
+        if !events.is_empty() {
+            let _ = device.emit(&events);
+        }
+    }
+}
+
+/// Handles the force feedback requests games made since the last call and returns
+/// the rumble commands for the client.
+fn read_force_feedback(device: &mut VirtualDevice, effects: &mut RumbleEffects) -> Vec<Rumble>{
+    let events: Vec<UInputEvent> = match device.fetch_events() {
+        Ok(events) => events.collect(),
+        Err(_) => return vec![],
+    };
+
+    let mut rumbles = vec![];
+
+    for event in events {
+        match event.kind() {
+            InputEventKind::UInput(code) if code == UInputEventType::UI_FF_UPLOAD.0 => {
+                if let Ok(upload) = device.process_ff_upload(event) {
+                    let effect = upload.effect();
+                    if let FFEffectKind::Rumble{ strong_magnitude, weak_magnitude } = effect.kind {
+                        effects.upload(upload.effect_id(), strong_magnitude, weak_magnitude, effect.replay.length);
+                    }
+                }
+            }
+            InputEventKind::UInput(code) if code == UInputEventType::UI_FF_ERASE.0 => {
+                if let Ok(erase) = device.process_ff_erase(event) {
+                    effects.erase(erase.effect_id() as i16);
+                }
+            }
+            InputEventKind::ForceFeedback(code) if code == FFEffectType::FF_GAIN.0 => effects.set_gain(event.value() as u16),
+            InputEventKind::ForceFeedback(id) => {
+                if let Some(rumble) = effects.play(id as i16, event.value()) {
+                    rumbles.push(rumble);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    rumbles
+}
@@ -0,0 +1,28 @@
+use std::str::FromStr;
+
+use evdev::Key;
+
+use crate::sh::bitmask_device::{self, BitmaskDevice};
+use crate::sh::config_file::ConfigFile;
+use crate::sh::key_names::device_key_name;
+use crate::sh::key_remap::KeyRemap;
+
+/// Finds the device key of a config name, like `capslock`, `playpause`, `btn_side` or
+/// `ctrl`, see `device_key_name`.
+pub fn key_from_name(name: &str) -> Option<Key>{
+    let name = device_key_name(name).to_uppercase();
+    match name.starts_with("BTN_") {
+        true => Key::from_str(&name).ok(),
+        false => Key::from_str(&format!("KEY_{}", name)).ok(),
+    }
+}
+
+/// The `[device <PROTOCOL>]` sections with evdev keys.
+pub fn load_bitmask_devices(config: &ConfigFile) -> Vec<BitmaskDevice<Key>>{
+    bitmask_device::load_bitmask_devices(config, key_from_name)
+}
+
+/// The `[remap]` and `[layer <name>]` sections with evdev keys.
+pub fn load_key_remap(config: &ConfigFile) -> KeyRemap<Key>{
+    KeyRemap::from_config(config, key_from_name)
+}
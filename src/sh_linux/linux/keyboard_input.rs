@@ -1,11 +1,215 @@
+use std::collections::{BTreeSet, HashSet};
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-use evdev::{AttributeSet, EventType, InputEvent, Key};
-use evdev::uinput::VirtualDeviceBuilder;
+use evdev::{AttributeSet, EventType, InputEvent, InputEventKind, Key, LedType};
 
-use crate::sh::key_input::KeyInputU128;
+use crate::sh::clients::Clients;
+use crate::sh::config_file::{ConfigFile, SharedConfig};
+use crate::sh::config_watcher::ReloadTracker;
+use crate::sh::key_behaviors::KeyBehaviors;
+use crate::sh::key_input::{DebounceSettings, GlitchReport, KeyInputU128};
+use crate::sh::key_names::MODIFIERS;
+use crate::sh::key_remap::KeyRemap;
+use crate::sh::keyboard_data::KeyboardMessage;
+use crate::sh::led_state::LedState;
+use crate::sh::stick_mapper::{StickMapper, StickSettings};
+use crate::sh::typematic::{RepeatMode, RepeatSettings, Typematic};
+use crate::sh_linux::linux::key_remap_config::{key_from_name, load_key_remap};
+use crate::sh_linux::linux::virtual_keyboard::VirtualKeyboard;
 
-pub fn start_keyboard_input(receiver: mpsc::Receiver<String>){
+// How often LED changes and stick pulsing are checked while no input arrives.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What the keyboard reads from the config, read again when it is reloaded.
+struct KeyboardSettings {
+    stick: StickSettings,
+    remap: KeyRemap<Key>,
+    behaviors: KeyBehaviors<Key>,
+    repeat: RepeatSettings,
+    debounce: DebounceSettings,
+}
+
+impl KeyboardSettings {
+    fn load(config: &ConfigFile) -> Self {
+        Self{
+            stick: StickSettings::from_config(config),
+            remap: load_key_remap(config),
+            behaviors: KeyBehaviors::from_config(config, key_from_name),
+            repeat: RepeatSettings::from_config(config),
+            debounce: DebounceSettings::from_config(config, "keyboard"),
+        }
+    }
+
+    /// Keys only reached through remapping, chords and tap-hold keys, e.g. media keys.
+    fn extra_keys(&self) -> BTreeSet<Key>{
+        self.remap.targets().into_iter().chain(self.behaviors.targets()).collect()
+    }
+}
+
+pub fn start_keyboard_input(receiver: mpsc::Receiver<KeyboardMessage>, config: SharedConfig, clients: Clients){
+    let mut settings = KeyboardSettings::load(&config.lock().unwrap());
+    let mut reloads = ReloadTracker::new(&config);
+    let mut stick = StickMapper::new(settings.stick.clone());
+    let mut typematic = Typematic::new(settings.repeat);
+    let started = Instant::now();
+
+    let mut device = create_device(&settings.extra_keys(), settings.repeat.mode);
+    let mut led_state = LedState::default();
+    let mut client_keys = 0u128;
+    let mut chord = 0u128;
+    let mut macro_keys = 0u128;
+    // Keys the device has pressed, released when the settings change.
+    let mut pressed: HashSet<u16> = HashSet::new();
+    // When the client last sent its state, server-side repeating takes held keys back once it is too old.
+    let mut client_updated = 0.0;
+
+    // Only the client's own mask bounces. It is debounced on its own, so the presses made
+    // here, like one frame chords and macro taps, are never taken for glitches.
+    let mut client_debounce = key_inputs(settings.debounce);
+    let mut keys = key_inputs(DebounceSettings::default());
+    let mut glitch_report = GlitchReport::new("keyboard");
+
+    loop {
+        let mut new_led_state = led_state;
+        for event in device.fetch_events().unwrap_or_default() {
+            if let InputEventKind::Led(led) = event.kind() {
+                let on = event.value() != 0;
+                match led {
+                    LedType::LED_NUML => new_led_state.num_lock = on,
+                    LedType::LED_CAPSL => new_led_state.caps_lock = on,
+                    LedType::LED_SCROLLL => new_led_state.scroll_lock = on,
+                    _ => {}
+                }
+            }
+        }
+
+        if new_led_state != led_state {
+            led_state = new_led_state;
+            clients.broadcast(&led_state.to_message());
+        }
+
+        // Debounced keys, tap-hold keys and chords wait for their timeout, which can be shorter than the poll interval.
+        let due = client_debounce.iter().filter_map(|key| key.next_due())
+            .chain([settings.behaviors.next_due(), typematic.next_due()].into_iter().flatten())
+            .reduce(f64::min);
+        let timeout = match due {
+            Some(due) => Duration::from_secs_f64((due - started.elapsed().as_secs_f64()).max(0.0)).min(POLL_INTERVAL),
+            None => POLL_INTERVAL,
+        };
+
+        match receiver.recv_timeout(timeout) {
+            Ok(KeyboardMessage::Keys(message)) => {
+                if let Ok(converted) = message.parse::<u128>() {
+                    client_keys = converted;
+                    client_updated = started.elapsed().as_secs_f64();
+                }
+            }
+            Ok(KeyboardMessage::Stick(message)) => {
+                stick.update(&message);
+                client_updated = started.elapsed().as_secs_f64();
+            }
+            Ok(KeyboardMessage::Chord(keys)) => chord = keys,
+            Ok(KeyboardMessage::Macro(keys)) => macro_keys = keys,
+            Ok(KeyboardMessage::Connected(client)) => clients.send(client, &led_state.to_message()),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                release_all(&mut device, &mut pressed);
+                break;
+            }
+        }
+
+        // Everything is released with the old settings, the keys still held are pressed again below.
+        if reloads.reloaded(&config) {
+            release_all(&mut device, &mut pressed);
+
+            let new_settings = KeyboardSettings::load(&config.lock().unwrap());
+            if new_settings.extra_keys() != settings.extra_keys() || new_settings.repeat.mode != settings.repeat.mode {
+                device = create_device(&new_settings.extra_keys(), new_settings.repeat.mode);
+                // The new device starts with all LEDs off.
+                led_state = LedState::default();
+                clients.broadcast(&led_state.to_message());
+            }
+            settings = new_settings;
+            stick = StickMapper::new(settings.stick.clone());
+            typematic = Typematic::new(settings.repeat);
+            client_debounce = key_inputs(settings.debounce);
+            keys = key_inputs(DebounceSettings::default());
+        }
+
+        let time = started.elapsed().as_secs_f64();
+        for key in client_debounce.iter_mut() {
+            key.get_event_at(client_keys, time);
+        }
+        glitch_report.add(client_debounce.iter_mut().map(|key| key.take_glitches()).sum(), time);
+        let debounced_keys = client_debounce.iter().fold(0, |mask, key| mask | key.pressed_mask());
+
+        // The stick and macros press keys through the same mask, so a key held by several is pressed once.
+        let stick_keys = stick.keys(time);
+        let key_state = debounced_keys | macro_keys | stick_keys;
+
+        // A chord is pressed on top of the held keys and released right after, with its
+        // modifiers in frames of their own so they are down before the other keys.
+        let mut key_states = vec![];
+        if chord != 0 {
+            key_states.extend([key_state | chord & MODIFIERS, key_state | chord, key_state | chord & MODIFIERS]);
+            chord = 0;
+        }
+        key_states.push(key_state);
+
+        let mut key_events: Vec<Vec<(Key, i32)>> = key_states.into_iter()
+            .map(|key_state| {
+                keys.iter_mut()
+                    .filter_map(|key| key.get_event_at(key_state, time))
+                    .flat_map(|(key, state)| settings.behaviors.event(key, state, time))
+                    .collect()
+            })
+            .collect();
+        key_events.push(settings.behaviors.tick(time));
+
+        for key_events in key_events {
+            let events: Vec<InputEvent> = key_events.into_iter()
+                .filter_map(|(key, state)| settings.remap.remap(key, state))
+                .inspect(|(key, state)| typematic.key_event(*key, *state, time))
+                .map(|(key, state)| InputEvent::new(EventType::KEY, key.code(), state))
+                .collect();
+
+            emit(&mut device, &mut pressed, &events);
+        }
+
+        // Keys held only by macros and chords are made here and can not go stale.
+        let updated = match debounced_keys | stick_keys {
+            0 => time,
+            _ => client_updated,
+        };
+        for (key, state) in typematic.tick(time, updated) {
+            emit(&mut device, &mut pressed, &[InputEvent::new(EventType::KEY, key.code(), state)]);
+        }
+    }
+}
+
+/// Emits the events and keeps track of the keys they leave pressed.
+fn emit(device: &mut VirtualKeyboard, pressed: &mut HashSet<u16>, events: &[InputEvent]){
+    for event in events {
+        match event.value() {
+            0 => pressed.remove(&event.code()),
+            _ => pressed.insert(event.code()),
+        };
+    }
+
+    if !events.is_empty() {
+        let _ = device.emit(events);
+    }
+}
+
+/// Releases every key the device has pressed.
+fn release_all(device: &mut VirtualKeyboard, pressed: &mut HashSet<u16>){
+    let released: Vec<InputEvent> = pressed.drain().map(|code| InputEvent::new(EventType::KEY, code, 0)).collect();
+    emit(device, pressed, &released);
+}
+
+/// Creates the device with the keys of the mask, LEDs and the `extra_keys` the settings can send.
+fn create_device(extra_keys: &BTreeSet<Key>, repeat: RepeatMode) -> VirtualKeyboard {
     let mut keys = AttributeSet::<Key>::new();
     // A - Z
     {
@@ -72,6 +276,7 @@ pub fn start_keyboard_input(receiver: mpsc::Receiver<String>){
         keys.insert(Key::KEY_LEFTSHIFT);
         keys.insert(Key::KEY_RIGHTSHIFT);
         keys.insert(Key::KEY_LEFTALT);
+        keys.insert(Key::KEY_LEFTMETA);
         keys.insert(Key::KEY_RIGHTALT);
         keys.insert(Key::KEY_LEFTCTRL);
         keys.insert(Key::KEY_RIGHTCTRL);
@@ -112,12 +317,22 @@ pub fn start_keyboard_input(receiver: mpsc::Receiver<String>){
         keys.insert(Key::KEY_F12);
     }
 
-    let mut device = VirtualDeviceBuilder::new().expect("Failed to create keyboard device")
-        .name("Virtual keyboard input")
-        .with_keys(&keys).expect("Failed to create key for keyboard input.")
-        .build().unwrap();
+    // Keys only reached through remapping, e.g. media keys.
+    for key in extra_keys {
+        keys.insert(*key);
+    }
+
+    let mut leds = AttributeSet::<LedType>::new();
+    leds.insert(LedType::LED_NUML);
+    leds.insert(LedType::LED_CAPSL);
+    leds.insert(LedType::LED_SCROLLL);
 
-    let mut keys = [
+    VirtualKeyboard::new("Virtual keyboard input", &keys, &leds, repeat == RepeatMode::Kernel).expect("Failed to create keyboard device")
+}
+
+/// Edge detection of the keys in the `KEYBOARD` key mask.
+fn key_inputs(debounce: DebounceSettings) -> [KeyInputU128<Key>; 86]{
+    [
         KeyInputU128::new(0, Key::KEY_0),
         KeyInputU128::new(1, Key::KEY_1),
         KeyInputU128::new(2, Key::KEY_2),
@@ -212,22 +427,5 @@ pub fn start_keyboard_input(receiver: mpsc::Receiver<String>){
         KeyInputU128::new(83, Key::KEY_PRINT),
         KeyInputU128::new(84, Key::KEY_SCROLLLOCK),
         KeyInputU128::new(85, Key::KEY_PAUSE),
-    ];
-
-    loop {
-        if let Ok(message) = receiver.recv() {
-            if let Ok(converted) = message.parse::<u128>() {
-
-                let mut events: Vec<InputEvent> = vec![];
-
-                for key in keys.iter_mut() {
-                    if let Some((key, state)) = key.get_event(converted){
-                        events.push(InputEvent::new(EventType::KEY, key.code(), state));
-                    }
-                }
-
-                let _ = device.emit(&events);
-            }
-        }
-    }
+    ].map(|key| key.with_debounce(debounce))
 }
@@ -1,67 +1,256 @@
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 use evdev::{AttributeSet, EventType, InputEvent, Key, RelativeAxisType};
-use evdev::uinput::VirtualDeviceBuilder;
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
 
-use crate::sh::key_input::KeyInputU8;
-use crate::sh::mouse_data::MouseData;
+use crate::sh::key_input::{DebounceSettings, GlitchReport, KeyInputU8};
+use crate::sh::key_names::key_bit;
+use crate::sh::config_file::SharedConfig;
+use crate::sh::config_watcher::ReloadTracker;
+use crate::sh::mouse_data::{MouseData, MouseMessage};
+use crate::sh::mouse_settings::MouseSettings;
+use crate::sh::pointer_smoothing::PointerSmoothing;
+use crate::sh_linux::linux::key_remap_config::load_key_remap;
 
-pub fn start_mouse_input(receiver: mpsc::Receiver<String>){
+// Modifier keys that can be held around mouse actions, so e.g. Ctrl+wheel comes from one device.
+const MODIFIERS: [(&str, Key); 4] = [
+    ("alt", Key::KEY_LEFTALT),
+    ("meta", Key::KEY_LEFTMETA),
+    ("ctrl", Key::KEY_LEFTCTRL),
+    ("shift", Key::KEY_LEFTSHIFT),
+];
+// Buttons clicked by mouse actions, by their bit.
+const CLICK_BUTTONS: [Key; 3] = [Key::BTN_LEFT, Key::BTN_RIGHT, Key::BTN_MIDDLE];
 
-    let speed_multiplier = 8.0;
-    let scroll_speed_multiplier = 10.0;
+pub fn start_mouse_input(receiver: mpsc::Receiver<MouseMessage>, mut smoothing: Option<PointerSmoothing>, config: SharedConfig){
 
-    let mut buttons = AttributeSet::<Key>::new();
-    buttons.insert(Key::BTN_LEFT);
-    buttons.insert(Key::BTN_RIGHT);
-    buttons.insert(Key::BTN_MIDDLE);
-
-    let mut motion = AttributeSet::<RelativeAxisType>::new();
-    motion.insert(RelativeAxisType::REL_X);
-    motion.insert(RelativeAxisType::REL_Y);
-    motion.insert(RelativeAxisType::REL_WHEEL);
+    let mut settings = MouseSettings::from_config(&config.lock().unwrap());
+    let mut remap = load_key_remap(&config.lock().unwrap());
+    let debounce = DebounceSettings::from_config(&config.lock().unwrap(), "mouse");
+    let mut reloads = ReloadTracker::new(&config);
 
-    let mut device = VirtualDeviceBuilder::new().expect("Failed to create virtual device: mouse")
-        .name("Virtual mouse input.")
-        .with_keys(&buttons).expect("Failed to create buttons for input mouse.")
-        .with_relative_axes(&motion).expect("Failed to create relative axes for mouse.")
-        .build().unwrap();
+    let mut device = create_device(&remap.targets());
+    let mut buttons = button_inputs(debounce);
+    let mut glitch_report = GlitchReport::new("mouse");
+    // The latest button state, passed again for debounced changes that were held back.
+    let mut last_button_state = 0u8;
 
-    let mut button_left = KeyInputU8::new(0, Key::BTN_LEFT);
-    let mut button_right = KeyInputU8::new(1, Key::BTN_RIGHT);
-    let mut button_middle = KeyInputU8::new(2, Key::BTN_MIDDLE);
+    let started = Instant::now();
+    let mut next_step = Instant::now();
 
     loop{
-        if let Ok(message) = receiver.recv(){
+        let smoothing_due = match &smoothing {
+            Some(smoothing) if !smoothing.is_idle() => Some(next_step),
+            _ => None,
+        };
+        let button_due = buttons.iter().filter_map(|button| button.next_due()).reduce(f64::min)
+            .map(|due| started + Duration::from_secs_f64(due.max(0.0)));
+
+        let message = match [smoothing_due, button_due].into_iter().flatten().min() {
+            Some(due) => match receiver.recv_timeout(due.saturating_duration_since(Instant::now())) {
+                Ok(message) => Some(message),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+        };
+        let time = started.elapsed().as_secs_f64();
+
+        // Held buttons are released with the old remapping and pressed again by the next data.
+        if reloads.reloaded(&config) {
+            let events: Vec<InputEvent> = buttons.iter_mut()
+                .filter_map(|button| button.release())
+                .filter_map(|(key, state)| remap.remap(key, state))
+                .map(|(key, state)| InputEvent::new(EventType::KEY, key.code(), state))
+                .collect();
+            if !events.is_empty() {
+                let _ = device.emit(&events);
+            }
+
+            let config = config.lock().unwrap();
+            let new_remap = load_key_remap(&config);
+            if new_remap.targets() != remap.targets() {
+                device = create_device(&new_remap.targets());
+            }
+            remap = new_remap;
+            settings = MouseSettings::from_config(&config);
+            buttons = button_inputs(DebounceSettings::from_config(&config, "mouse"));
+        }
+
+        if message.is_none() {
+            let events: Vec<InputEvent> = buttons.iter_mut()
+                .filter_map(|button| button.get_event_at(last_button_state, time))
+                .filter_map(|(key, state)| remap.remap(key, state))
+                .map(|(key, state)| InputEvent::new(EventType::KEY, key.code(), state))
+                .collect();
+            if !events.is_empty() {
+                let _ = device.emit(&events);
+            }
+        }
+
+        if let Some(MouseMessage::Settings(data)) = &message {
+            settings.apply(data);
+            if let Err(error) = settings.save(&config) {
+                println!("Failed to save mouse settings.");
+                println!("{}", error);
+            }
+        }
+
+        if let Some(MouseMessage::Motion(delta_x, delta_y)) = message {
+            match &mut smoothing {
+                Some(smoothing) => {
+                    if smoothing.is_idle() {
+                        next_step = Instant::now();
+                    }
+                    smoothing.push(delta_x, delta_y);
+                }
+                None => {
+                    let mut events: Vec<InputEvent> = vec![];
+                    push_motion(&mut events, delta_x as i32, delta_y as i32);
+                    if !events.is_empty() {
+                        let _ = device.emit(&events);
+                    }
+                }
+            }
+        }
+
+        if let Some(MouseMessage::Action{ modifiers, wheel, buttons: clicks }) = message {
+            let modifiers: Vec<Key> = MODIFIERS.iter()
+                .filter(|(name, _)| key_bit(name).map(|bit| modifiers & 1 << bit != 0).unwrap_or(false))
+                .map(|(_, key)| *key)
+                .collect();
+            let clicks: Vec<Key> = CLICK_BUTTONS.iter().enumerate()
+                .filter(|(bit, _)| clicks & 1 << bit != 0)
+                .map(|(_, key)| *key)
+                .collect();
+
+            let mut press = key_events(&clicks, 1);
+            if wheel != 0 {
+                press.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, wheel));
+            }
+
+            for events in [key_events(&modifiers, 1), press, key_events(&clicks, 0), key_events(&modifiers, 0)] {
+                if !events.is_empty() {
+                    let _ = device.emit(&events);
+                }
+            }
+        }
+
+        if let Some(MouseMessage::Data(message)) = message {
             let mouse_state = MouseData::new(message);
+            let button_state = settings.map_buttons(mouse_state.button_state);
+            last_button_state = button_state;
 
             let mut events: Vec<InputEvent> = vec![];
+            let (delta_x, delta_y) = settings.motion(mouse_state.delta_x, -mouse_state.delta_y);
 
-            if mouse_state.delta_x.abs() > 0.0 {
-                events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, (mouse_state.delta_x * speed_multiplier) as i32));
-            }
+            match &mut smoothing {
+                Some(smoothing) => {
+                    if smoothing.is_idle() {
+                        next_step = Instant::now();
+                    }
+
+                    if delta_x.abs() > 0.0 || delta_y.abs() > 0.0 {
+                        smoothing.push(delta_x, delta_y);
+                    }
 
-            if mouse_state.delta_y.abs() > 0.0 {
-                events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_Y.0, -(mouse_state.delta_y * speed_multiplier) as i32));
+                    // Pay out the pending motion first so a click lands where the pointer was headed.
+                    if buttons.iter().any(|button| button.would_change(button_state)) {
+                        if let Some((x, y)) = smoothing.flush() {
+                            push_motion(&mut events, x, y);
+                        }
+                    }
+                }
+                None => push_motion(&mut events, delta_x as i32, delta_y as i32),
             }
 
             if mouse_state.delta_wheel.abs() > 0f32 {
-                events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, (mouse_state.delta_wheel * scroll_speed_multiplier) as i32));
+                events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, settings.wheel(mouse_state.delta_wheel) as i32));
             }
 
-            if let Some((key, state)) = button_left.get_event(mouse_state.button_state){
-                events.push(InputEvent::new(EventType::KEY, key.code(), state));
+            for button in buttons.iter_mut() {
+                if let Some((key, state)) = button.get_event_at(button_state, time).and_then(|(key, state)| remap.remap(key, state)){
+                    events.push(InputEvent::new(EventType::KEY, key.code(), state));
+                }
             }
+            glitch_report.add(buttons.iter_mut().map(|button| button.take_glitches()).sum(), time);
 
-            if let Some((key, state)) = button_right.get_event(mouse_state.button_state){
-                events.push(InputEvent::new(EventType::KEY, key.code(), state));
+            if !events.is_empty() {
+                let _ = device.emit(&events);
             }
+        }
 
-            if let Some((key, state)) = button_middle.get_event(mouse_state.button_state){
-                events.push(InputEvent::new(EventType::KEY, key.code(), state));
+        if let Some(smoothing) = &mut smoothing {
+            if !smoothing.is_idle() && Instant::now() >= next_step {
+                if let Some((x, y)) = smoothing.step() {
+                    let mut events: Vec<InputEvent> = vec![];
+                    push_motion(&mut events, x, y);
+                    let _ = device.emit(&events);
+                }
+                next_step += smoothing.interval();
             }
-
-            let _ = device.emit(&events);
         }
     }
 }
+
+/// Creates the device with the mouse buttons, modifiers and the keys the remapping can send.
+fn create_device(remap_targets: &[Key]) -> VirtualDevice {
+    let mut buttons = AttributeSet::<Key>::new();
+    buttons.insert(Key::BTN_LEFT);
+    buttons.insert(Key::BTN_RIGHT);
+    buttons.insert(Key::BTN_MIDDLE);
+    buttons.insert(Key::BTN_SIDE);
+    buttons.insert(Key::BTN_EXTRA);
+    buttons.insert(Key::BTN_FORWARD);
+    buttons.insert(Key::BTN_BACK);
+    buttons.insert(Key::BTN_TASK);
+    for (_, key) in MODIFIERS {
+        buttons.insert(key);
+    }
+    for key in remap_targets {
+        buttons.insert(*key);
+    }
+
+    let mut motion = AttributeSet::<RelativeAxisType>::new();
+    motion.insert(RelativeAxisType::REL_X);
+    motion.insert(RelativeAxisType::REL_Y);
+    motion.insert(RelativeAxisType::REL_WHEEL);
+
+    VirtualDeviceBuilder::new().expect("Failed to create virtual device: mouse")
+        .name("Virtual mouse input.")
+        .with_keys(&buttons).expect("Failed to create buttons for input mouse.")
+        .with_relative_axes(&motion).expect("Failed to create relative axes for mouse.")
+        .build().unwrap()
+}
+
+/// Edge detection of the buttons in the mouse data, by their bit.
+fn button_inputs(debounce: DebounceSettings) -> [KeyInputU8<Key>; 8]{
+    [
+        KeyInputU8::new(0, Key::BTN_LEFT),
+        KeyInputU8::new(1, Key::BTN_RIGHT),
+        KeyInputU8::new(2, Key::BTN_MIDDLE),
+        KeyInputU8::new(3, Key::BTN_SIDE),
+        KeyInputU8::new(4, Key::BTN_EXTRA),
+        KeyInputU8::new(5, Key::BTN_FORWARD),
+        KeyInputU8::new(6, Key::BTN_BACK),
+        KeyInputU8::new(7, Key::BTN_TASK),
+    ].map(|button| button.with_debounce(debounce))
+}
+
+fn push_motion(events: &mut Vec<InputEvent>, x: i32, y: i32){
+    if x != 0 {
+        events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, x));
+    }
+
+    if y != 0 {
+        events.push(InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_Y.0, y));
+    }
+}
+
+fn key_events(keys: &[Key], state: i32) -> Vec<InputEvent>{
+    keys.iter().map(|key| InputEvent::new(EventType::KEY, key.code(), state)).collect()
+}
@@ -1,38 +1,176 @@
+use std::collections::{BTreeSet, HashMap};
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
 use evdev::{AttributeSet, EventType, InputEvent, Key};
-use evdev::uinput::VirtualDeviceBuilder;
-use crate::sh::key_input::KeyInputU8;
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use crate::sh::clients::{ClientId, Clients};
+use crate::sh::config_file::{ConfigFile, SharedConfig};
+use crate::sh::config_watcher::ReloadTracker;
+use crate::sh::key_input::{DebounceSettings, GlitchReport, LayoutInput};
+use crate::sh::key_remap::KeyRemap;
+use crate::sh::osu_mode::{OsuMessage, OsuMode};
+use crate::sh::tap_stats::TapStats;
+use crate::sh_linux::linux::key_remap_config::{key_from_name, load_key_remap};
 
-pub fn start_osu_input(receiver: mpsc::Receiver<String>){
+/// Keys and tap statistics of one client, in the layout of its mode.
+struct OsuClient {
+    mode: OsuMode,
+    keys: LayoutInput<Key>,
+    stats: TapStats,
+}
 
-    let mut keys = AttributeSet::<Key>::new();
-    keys.insert(Key::KEY_Z);
-    keys.insert(Key::KEY_X);
+impl OsuClient {
+    fn new(mode: OsuMode, layouts: &HashMap<OsuMode, Vec<Key>>, debounce: DebounceSettings) -> Self {
+        let layout = &layouts[&mode];
+        Self{ mode, keys: LayoutInput::new(layout, debounce), stats: TapStats::new(layout.len()) }
+    }
 
-    let mut device = VirtualDeviceBuilder::new().expect("Failed to create virtual device: osu!")
-        .name("Virtual osu! input")
-        .with_keys(&keys).expect("Failed to init keys for osu! input.")
-        .build().unwrap();
+    // Statistics are kept for the keys of the layout, before they are remapped.
+    fn push_key_events(&mut self, events: &mut Vec<InputEvent>, remap: &mut KeyRemap<Key>, time: f64){
+        let key_events = self.keys.get_events_at(time);
+        self.push(key_events, events, remap, time);
+    }
+
+    /// Releases all keys without debouncing, before the client goes away or the settings change.
+    fn release(&mut self, events: &mut Vec<InputEvent>, remap: &mut KeyRemap<Key>, time: f64){
+        let key_events = self.keys.release();
+        self.push(key_events, events, remap, time);
+    }
+
+    fn push(&mut self, key_events: Vec<(usize, Key, i32)>, events: &mut Vec<InputEvent>, remap: &mut KeyRemap<Key>, time: f64){
+        for (index, key, state) in key_events {
+            self.stats.record(index, state == 1, time);
+            if let Some((key, state)) = remap.remap(key, state) {
+                events.push(InputEvent::new(EventType::KEY, key.code(), state));
+            }
+        }
+    }
+}
+
+pub fn start_osu_input(receiver: mpsc::Receiver<OsuMessage>, config: SharedConfig, connected: Clients){
+
+    let load = |config: &SharedConfig| {
+        let config = config.lock().unwrap();
+        let layouts: HashMap<OsuMode, Vec<Key>> = OsuMode::ALL.iter().map(|mode| (*mode, layout_keys(*mode, &config))).collect();
+        (OsuMode::from_config(&config), layouts, load_key_remap(&config), DebounceSettings::from_config(&config, "osu"))
+    };
+    let (mut default_mode, mut layouts, mut remap, mut debounce) = load(&config);
+    let mut reloads = ReloadTracker::new(&config);
+    let mut glitch_report = GlitchReport::new("osu!");
 
-    let mut z_key = KeyInputU8::new(0, Key::KEY_Z);
-    let mut x_key = KeyInputU8::new(1, Key::KEY_X);
+    let mut device = create_device(&device_keys(&layouts, &remap));
+
+    // Every client decodes its mask with the layout of its own mode.
+    let mut clients: HashMap<ClientId, OsuClient> = HashMap::new();
+    let started = Instant::now();
 
     loop{
-        if let Ok(message) = receiver.recv(){
-            if let Ok(converted) = message.parse::<u8>(){
+        let due = clients.values().filter_map(|osu_client| osu_client.keys.next_due()).reduce(f64::min);
+        let message = match due {
+            Some(due) => match receiver.recv_timeout(Duration::from_secs_f64((due - started.elapsed().as_secs_f64()).max(0.0))) {
+                Ok(message) => Some(message),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+        };
 
-                let mut events: Vec<InputEvent> = vec![];
+        let time = started.elapsed().as_secs_f64();
+        let mut events: Vec<InputEvent> = vec![];
 
-                if let Some((key, state)) = z_key.get_event(converted) {
-                    events.push(InputEvent::new(EventType::KEY, key.code(), state));
+        if let Some(message) = message {
+            match message {
+                OsuMessage::Keys(client, message) => {
+                    if let Ok(converted) = message.parse::<u128>(){
+                        let osu_client = clients.entry(client).or_insert_with(|| OsuClient::new(default_mode, &layouts, debounce));
+                        osu_client.keys.set_mask(converted);
+                        osu_client.push_key_events(&mut events, &mut remap, time);
+                    }
                 }
-
-                if let Some((key, state)) = x_key.get_event(converted) {
-                    events.push(InputEvent::new(EventType::KEY, key.code(), state));
+                OsuMessage::Mode(client, name) => match OsuMode::from_name(&name) {
+                    Some(mode) => {
+                        // Keys held in the old layout are released before switching.
+                        if let Some(mut old_client) = clients.insert(client, OsuClient::new(mode, &layouts, debounce)) {
+                            old_client.release(&mut events, &mut remap, time);
+                        }
+                    }
+                    None => println!("Unknown osu! mode: {}", name),
+                },
+                OsuMessage::Stats(client) => {
+                    let osu_client = clients.entry(client).or_insert_with(|| OsuClient::new(default_mode, &layouts, debounce));
+                    connected.send(client, &osu_client.stats.summary().to_message());
+                }
+                OsuMessage::Disconnected(client) => {
+                    if let Some(mut old_client) = clients.remove(&client) {
+                        old_client.release(&mut events, &mut remap, time);
+                        let summary = old_client.stats.summary();
+                        if summary.taps > 0 {
+                            println!("osu! tap statistics: {}", summary);
+                        }
+                    }
                 }
+            }
+        }
 
-                let _ = device.emit(&events).unwrap();
+        // Keys are released with the old settings and pressed again below with the new ones,
+        // clients keep their mode and statistics.
+        if reloads.reloaded(&config) {
+            for osu_client in clients.values_mut() {
+                osu_client.release(&mut events, &mut remap, time);
             }
+            if !events.is_empty() {
+                device.emit(&std::mem::take(&mut events)).unwrap();
+            }
+
+            let old_keys = device_keys(&layouts, &remap);
+            (default_mode, layouts, remap, debounce) = load(&config);
+            if device_keys(&layouts, &remap) != old_keys {
+                device = create_device(&device_keys(&layouts, &remap));
+            }
+            for osu_client in clients.values_mut() {
+                osu_client.keys = LayoutInput::new(&layouts[&osu_client.mode], debounce).with_mask(osu_client.keys.mask());
+            }
+        }
+
+        // Debounced changes that were held back are sent once due.
+        for osu_client in clients.values_mut() {
+            osu_client.push_key_events(&mut events, &mut remap, time);
+            glitch_report.add(osu_client.keys.take_glitches(), time);
+        }
+
+        if !events.is_empty() {
+            device.emit(&events).unwrap();
         }
     }
 }
+
+/// Keys of all layouts and the ones the remapping can send.
+fn device_keys(layouts: &HashMap<OsuMode, Vec<Key>>, remap: &KeyRemap<Key>) -> BTreeSet<Key>{
+    layouts.values().flatten().chain(remap.targets().iter()).copied().collect()
+}
+
+fn create_device(device_keys: &BTreeSet<Key>) -> VirtualDevice {
+    let mut keys = AttributeSet::<Key>::new();
+    for key in device_keys {
+        keys.insert(*key);
+    }
+
+    VirtualDeviceBuilder::new().expect("Failed to create virtual device: osu!")
+        .name("Virtual osu! input")
+        .with_keys(&keys).expect("Failed to init keys for osu! input.")
+        .build().unwrap()
+}
+
+fn layout_keys(mode: OsuMode, config: &ConfigFile) -> Vec<Key>{
+    let parse = |names: Vec<String>| -> Option<Vec<Key>> {
+        names.iter().map(|name| key_from_name(name)).collect()
+    };
+
+    parse(mode.keys(config)).unwrap_or_else(|| {
+        println!("Unknown key in the osu! {} layout, using the default one.", mode.name());
+        parse(mode.keys(&ConfigFile::default())).unwrap_or_default()
+    })
+}
@@ -0,0 +1,110 @@
+use std::sync::mpsc;
+
+use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, PropType, UinputAbsSetup};
+use evdev::uinput::VirtualDeviceBuilder;
+
+use crate::sh::key_input::KeyInputU8;
+use crate::sh::pen_data::{PenData, PenTool};
+
+// Reported as a 160 x 100 mm tablet.
+const WIDTH: i32 = 16000;
+const HEIGHT: i32 = 10000;
+const UNITS_PER_MM: i32 = 100;
+const MAX_PRESSURE: i32 = 4095;
+// Tilt is reported in degrees, the kernel expects the resolution in units per radian.
+const UNITS_PER_RADIAN: i32 = 57;
+
+pub fn start_pen_input(receiver: mpsc::Receiver<String>){
+
+    let mut keys = AttributeSet::<Key>::new();
+    keys.insert(Key::BTN_TOOL_PEN);
+    keys.insert(Key::BTN_TOOL_RUBBER);
+    keys.insert(Key::BTN_TOUCH);
+    keys.insert(Key::BTN_STYLUS);
+    keys.insert(Key::BTN_STYLUS2);
+
+    let mut properties = AttributeSet::<PropType>::new();
+    properties.insert(PropType::POINTER);
+
+    let mut device = VirtualDeviceBuilder::new().expect("Failed to create virtual device: pen")
+        .name("Virtual pen input")
+        .with_keys(&keys).expect("Failed to create buttons for pen input.")
+        .with_properties(&properties).expect("Failed to set properties for pen input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, AbsInfo::new(0, 0, WIDTH, 0, 0, UNITS_PER_MM))).expect("Failed to create axes for pen input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, AbsInfo::new(0, 0, HEIGHT, 0, 0, UNITS_PER_MM))).expect("Failed to create axes for pen input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_PRESSURE, AbsInfo::new(0, 0, MAX_PRESSURE, 0, 0, 0))).expect("Failed to create axes for pen input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_TILT_X, AbsInfo::new(0, -90, 90, 0, 0, UNITS_PER_RADIAN))).expect("Failed to create axes for pen input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_TILT_Y, AbsInfo::new(0, -90, 90, 0, 0, UNITS_PER_RADIAN))).expect("Failed to create axes for pen input.")
+        .build().unwrap();
+
+    let mut buttons = [
+        KeyInputU8::new(0, Key::BTN_STYLUS),
+        KeyInputU8::new(1, Key::BTN_STYLUS2),
+    ];
+    let mut tool = PenTool::None;
+    let mut touching = false;
+
+    // Ends once the sender is dropped.
+    while let Ok(message) = receiver.recv(){
+        let pen_state = PenData::new(message);
+
+        let mut events: Vec<InputEvent> = vec![];
+
+        // Leaving proximity, or switching between tip and eraser, lifts the old tool first.
+        if tool != PenTool::None && tool != pen_state.tool {
+            if touching {
+                events.push(InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), 0));
+                events.push(abs_event(AbsoluteAxisType::ABS_PRESSURE, 0));
+                touching = false;
+            }
+            for button in buttons.iter_mut() {
+                if let Some((key, state)) = button.get_event(0) {
+                    events.push(InputEvent::new(EventType::KEY, key.code(), state));
+                }
+            }
+            events.push(InputEvent::new(EventType::KEY, tool_key(tool).code(), 0));
+            let _ = device.emit(&events);
+            events.clear();
+        }
+
+        if pen_state.tool == PenTool::None {
+            tool = PenTool::None;
+            continue;
+        }
+
+        events.push(abs_event(AbsoluteAxisType::ABS_X, (pen_state.x * WIDTH as f32).round() as i32));
+        events.push(abs_event(AbsoluteAxisType::ABS_Y, (pen_state.y * HEIGHT as f32).round() as i32));
+        events.push(abs_event(AbsoluteAxisType::ABS_PRESSURE, (pen_state.pressure * MAX_PRESSURE as f32).round() as i32));
+        events.push(abs_event(AbsoluteAxisType::ABS_TILT_X, pen_state.tilt_x.round() as i32));
+        events.push(abs_event(AbsoluteAxisType::ABS_TILT_Y, pen_state.tilt_y.round() as i32));
+
+        if tool != pen_state.tool {
+            tool = pen_state.tool;
+            events.push(InputEvent::new(EventType::KEY, tool_key(tool).code(), 1));
+        }
+
+        if touching != pen_state.is_touching() {
+            touching = pen_state.is_touching();
+            events.push(InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), touching as i32));
+        }
+
+        for button in buttons.iter_mut() {
+            if let Some((key, state)) = button.get_event(pen_state.button_state) {
+                events.push(InputEvent::new(EventType::KEY, key.code(), state));
+            }
+        }
+
+        let _ = device.emit(&events);
+    }
+}
+
+fn tool_key(tool: PenTool) -> Key {
+    match tool {
+        PenTool::Rubber => Key::BTN_TOOL_RUBBER,
+        _ => Key::BTN_TOOL_PEN,
+    }
+}
+
+fn abs_event(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
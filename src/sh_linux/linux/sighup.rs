@@ -0,0 +1,20 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::sys::signal::{signal, SigHandler, Signal};
+
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_: libc::c_int){
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes SIGHUP request a configuration reload instead of ending the server, the
+/// returned flag is set when it arrives.
+pub fn watch_sighup() -> &'static AtomicBool {
+    // Only stores to an atomic, which is safe in a signal handler.
+    if let Err(error) = unsafe { signal(Signal::SIGHUP, SigHandler::Handler(request_reload)) } {
+        println!("Failed to handle SIGHUP, the configuration is only reloaded when the file changes.");
+        println!("{}", error);
+    }
+    &RELOAD_REQUESTED
+}
@@ -0,0 +1,121 @@
+use std::sync::mpsc;
+
+use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, PropType, UinputAbsSetup};
+use evdev::uinput::VirtualDeviceBuilder;
+
+use crate::sh::key_input::KeyInputU8;
+use crate::sh::touch_data::{SlotEvent, TouchData, TouchSlots};
+
+const SLOTS: usize = 5;
+// Reported as a 100 x 60 mm pad, libinput needs a resolution to scale its gestures.
+const WIDTH: i32 = 2000;
+const HEIGHT: i32 = 1200;
+const UNITS_PER_MM: i32 = 20;
+
+pub fn start_touchpad_input(receiver: mpsc::Receiver<String>){
+
+    let mut keys = AttributeSet::<Key>::new();
+    keys.insert(Key::BTN_LEFT);
+    keys.insert(Key::BTN_TOUCH);
+    keys.insert(Key::BTN_TOOL_FINGER);
+    keys.insert(Key::BTN_TOOL_DOUBLETAP);
+    keys.insert(Key::BTN_TOOL_TRIPLETAP);
+    keys.insert(Key::BTN_TOOL_QUADTAP);
+    keys.insert(Key::BTN_TOOL_QUINTTAP);
+
+    let mut properties = AttributeSet::<PropType>::new();
+    properties.insert(PropType::POINTER);
+    properties.insert(PropType::BUTTONPAD);
+
+    let x_axis = AbsInfo::new(0, 0, WIDTH, 0, 0, UNITS_PER_MM);
+    let y_axis = AbsInfo::new(0, 0, HEIGHT, 0, 0, UNITS_PER_MM);
+
+    let mut device = VirtualDeviceBuilder::new().expect("Failed to create virtual device: touchpad")
+        .name("Virtual touchpad input")
+        .with_keys(&keys).expect("Failed to create buttons for touchpad input.")
+        .with_properties(&properties).expect("Failed to set properties for touchpad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, x_axis)).expect("Failed to create axes for touchpad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, y_axis)).expect("Failed to create axes for touchpad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_SLOT, AbsInfo::new(0, 0, SLOTS as i32 - 1, 0, 0, 0))).expect("Failed to create axes for touchpad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_TRACKING_ID, AbsInfo::new(0, -1, i32::MAX, 0, 0, 0))).expect("Failed to create axes for touchpad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_POSITION_X, x_axis)).expect("Failed to create axes for touchpad input.")
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_POSITION_Y, y_axis)).expect("Failed to create axes for touchpad input.")
+        .build().unwrap();
+
+    let mut slots = TouchSlots::new(SLOTS);
+    let mut button_left = KeyInputU8::new(0, Key::BTN_LEFT);
+    let mut fingers = 0;
+
+    // Ends once the sender is dropped.
+    while let Ok(message) = receiver.recv(){
+        let touch_state = TouchData::new(message);
+
+        let mut events: Vec<InputEvent> = vec![];
+
+        for slot_event in slots.update(&touch_state.contacts) {
+            match slot_event {
+                SlotEvent::Down{ slot, tracking_id, x, y } => {
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_SLOT, slot as i32));
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_TRACKING_ID, tracking_id));
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_POSITION_X, scale(x, WIDTH)));
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_POSITION_Y, scale(y, HEIGHT)));
+                }
+                SlotEvent::Move{ slot, x, y } => {
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_SLOT, slot as i32));
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_POSITION_X, scale(x, WIDTH)));
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_POSITION_Y, scale(y, HEIGHT)));
+                }
+                SlotEvent::Up{ slot } => {
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_SLOT, slot as i32));
+                    events.push(abs_event(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1));
+                }
+            }
+        }
+
+        if let Some((x, y)) = slots.primary() {
+            events.push(abs_event(AbsoluteAxisType::ABS_X, scale(x, WIDTH)));
+            events.push(abs_event(AbsoluteAxisType::ABS_Y, scale(y, HEIGHT)));
+        }
+
+        let active = slots.active();
+        if active != fingers {
+            if (fingers == 0) != (active == 0) {
+                events.push(InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), (active > 0) as i32));
+            }
+            if let Some(tool) = finger_tool(fingers) {
+                events.push(InputEvent::new(EventType::KEY, tool.code(), 0));
+            }
+            if let Some(tool) = finger_tool(active) {
+                events.push(InputEvent::new(EventType::KEY, tool.code(), 1));
+            }
+            fingers = active;
+        }
+
+        if let Some((key, state)) = button_left.get_event(touch_state.button_state) {
+            events.push(InputEvent::new(EventType::KEY, key.code(), state));
+        }
+
+        if !events.is_empty() {
+            let _ = device.emit(&events);
+        }
+    }
+}
+
+fn finger_tool(fingers: usize) -> Option<Key> {
+    match fingers {
+        0 => None,
+        1 => Some(Key::BTN_TOOL_FINGER),
+        2 => Some(Key::BTN_TOOL_DOUBLETAP),
+        3 => Some(Key::BTN_TOOL_TRIPLETAP),
+        4 => Some(Key::BTN_TOOL_QUADTAP),
+        _ => Some(Key::BTN_TOOL_QUINTTAP),
+    }
+}
+
+fn abs_event(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
+
+fn scale(value: f32, maximum: i32) -> i32 {
+    (value * maximum as f32).round() as i32
+}
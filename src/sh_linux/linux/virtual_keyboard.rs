@@ -0,0 +1,99 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+
+use evdev::{AttributeSetRef, BusType, EventType, InputEvent, Key, LedType};
+use nix::sys::ioctl::ioctl_param_type;
+use nix::{ioctl_none, ioctl_write_int, ioctl_write_ptr};
+
+ioctl_none!(ui_dev_create, b'U', 1);
+ioctl_write_ptr!(ui_dev_setup, b'U', 3, libc::uinput_setup);
+ioctl_write_int!(ui_set_evbit, b'U', 100);
+ioctl_write_int!(ui_set_keybit, b'U', 101);
+ioctl_write_int!(ui_set_ledbit, b'U', 105);
+
+const UINPUT_PATH: &str = "/dev/uinput";
+const EVENT_BATCH_SIZE: usize = 32;
+
+/// A uinput keyboard that can also declare LEDs, which evdev's `VirtualDeviceBuilder`
+/// has no support for.
+///
+/// The device is opened non-blocking, so `fetch_events` returns right away when the
+/// system has not sent anything. With `kernel_repeat` the device declares `EV_REP`, so
+/// the kernel repeats held keys, without it repeating is left to the compositor.
+pub struct VirtualKeyboard {
+    file: File,
+}
+
+impl VirtualKeyboard {
+    pub fn new(name: &str, keys: &AttributeSetRef<Key>, leds: &AttributeSetRef<LedType>, kernel_repeat: bool) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+
+        unsafe {
+            ui_set_evbit(fd, EventType::KEY.0 as ioctl_param_type)?;
+            for key in keys.iter() {
+                ui_set_keybit(fd, key.code() as ioctl_param_type)?;
+            }
+
+            if kernel_repeat {
+                ui_set_evbit(fd, EventType::REPEAT.0 as ioctl_param_type)?;
+            }
+
+            if leds.iter().next().is_some() {
+                ui_set_evbit(fd, EventType::LED.0 as ioctl_param_type)?;
+                for led in leds.iter() {
+                    ui_set_ledbit(fd, led.0 as ioctl_param_type)?;
+                }
+            }
+        }
+
+        let mut setup: libc::uinput_setup = unsafe { mem::zeroed() };
+        setup.id = libc::input_id{ bustype: BusType::BUS_USB.0, vendor: 0x1234, product: 0x5678, version: 0x111 };
+        // Keep the terminating zero the setup was initialised with.
+        for (target, byte) in setup.name.iter_mut().zip(name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1)) {
+            *target = byte as libc::c_char;
+        }
+
+        unsafe {
+            ui_dev_setup(fd, &setup)?;
+            ui_dev_create(fd)?;
+        }
+
+        Ok(Self{ file })
+    }
+
+    /// Writes the events followed by a `SYN_REPORT`.
+    pub fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let mut raw: Vec<libc::input_event> = events.iter().map(|event| *event.as_ref()).collect();
+        raw.push(*InputEvent::new(EventType::SYNCHRONIZATION, 0, 0).as_ref());
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(raw.as_ptr() as *const u8, mem::size_of_val(raw.as_slice()))
+        };
+        self.file.write_all(bytes)
+    }
+
+    /// Returns the events the system sent to the device since the last call, like LED changes.
+    pub fn fetch_events(&mut self) -> io::Result<Vec<InputEvent>> {
+        let size = mem::size_of::<libc::input_event>();
+        let mut buffer = vec![0u8; size * EVENT_BATCH_SIZE];
+
+        let read = match self.file.read(&mut buffer) {
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(vec![]),
+            Err(error) => return Err(error),
+        };
+
+        Ok(buffer[..read].chunks_exact(size)
+            .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const libc::input_event) })
+            .map(InputEvent::from)
+            .collect())
+    }
+}
@@ -2,12 +2,13 @@ use std::sync::mpsc;
 use winsafe;
 use winsafe::co::VK;
 use crate::sh::key_input::KeyInputU8;
-use crate::sh::mouse_data::MouseData;
+use crate::sh::config_file::SharedConfig;
+use crate::sh::mouse_data::{MouseData, MouseMessage};
+use crate::sh::mouse_settings::MouseSettings;
 
-pub fn start_mouse_input(receiver: mpsc::Receiver<String>){
+pub fn start_mouse_input(receiver: mpsc::Receiver<MouseMessage>, config: SharedConfig){
 
-    let speed_multiplier = 8.0;
-    let scroll_speed_multiplier = 10.0;
+    let mut settings = MouseSettings::from_config(&config.lock().unwrap());
 
     let mut delta_x = 0i32;
     let mut delta_y = 0i32;
@@ -17,26 +18,44 @@ pub fn start_mouse_input(receiver: mpsc::Receiver<String>){
     let mut button_middle = KeyInputU8::new(2, VK::MBUTTON);
 
     loop {
-        if let Ok(message) = receiver.recv() {
+        let message = receiver.recv();
+
+        if let Ok(MouseMessage::Settings(data)) = &message {
+            settings.apply(data);
+            if let Err(error) = settings.save(&config) {
+                println!("Failed to save mouse settings.");
+                println!("{}", error);
+            }
+        }
+
+        if let Ok(MouseMessage::Motion(motion_x, motion_y)) = message {
+            if let Ok(current_position) = winsafe::GetCursorPos(){
+                let _ = winsafe::SetCursorPos(current_position.x + motion_x as i32, current_position.y + motion_y as i32);
+            }
+        }
+
+        if let Ok(MouseMessage::Data(message)) = message {
             let mouse_state = MouseData::new(message);
+            let (motion_x, motion_y) = settings.motion(mouse_state.delta_x, mouse_state.delta_y);
+            let button_state = settings.map_buttons(mouse_state.button_state);
 
-            if mouse_state.delta_x.abs() > 0.0 {
-                delta_x = (mouse_state.delta_x * speed_multiplier) as i32;
+            if motion_x.abs() > 0.0 {
+                delta_x = motion_x as i32;
             }
 
-            if mouse_state.delta_y.abs() > 0.0 {
-                delta_y = (mouse_state.delta_y * speed_multiplier) as i32;
+            if motion_y.abs() > 0.0 {
+                delta_y = motion_y as i32;
             }
 
-            if let Some((key, state)) = button_left.get_event(mouse_state.button_state){
+            if let Some((key, state)) = button_left.get_event(button_state){
                 //TODO: Press/Release left mouse.
             }
 
-            if let Some((key, state)) = button_right.get_event(mouse_state.button_state){
+            if let Some((key, state)) = button_right.get_event(button_state){
                 //TODO: Press/Release right mouse.
             }
 
-            if let Some((key, state)) = button_middle.get_event(mouse_state.button_state){
+            if let Some((key, state)) = button_middle.get_event(button_state){
                 //TODO: Press/Release middle mouse.
             }
 